@@ -0,0 +1,30 @@
+use befunge93_rs::Interpreter;
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    io::Cursor,
+    time::{Duration, Instant},
+};
+
+/// Counts down from 39^3, doing some straight-line arithmetic on every
+/// iteration.
+const PROGRAM: &str = "\"'\"::**v\nv      <\n>11+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+1+$:!#@_1-v\n^                                                                     <";
+
+fn run(fast_path: bool) -> Duration {
+    let input = Cursor::new(Vec::new());
+    let output = Cursor::new(Vec::new());
+    let gen = StdRng::seed_from_u64(123);
+    let mut interpreter = Interpreter::new(input, output, gen);
+    interpreter.set_fast_path(fast_path);
+    interpreter.load_program(PROGRAM).unwrap();
+
+    let start = Instant::now();
+    interpreter.run().unwrap();
+    start.elapsed()
+}
+
+fn main() {
+    for fast_path in [false, true] {
+        let best = (0..5).map(|_| run(fast_path)).min().unwrap();
+        println!("fast path {}: {:?}", fast_path, best);
+    }
+}
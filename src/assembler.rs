@@ -0,0 +1,99 @@
+/// A piece of a linear program, see [`assemble`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Instruction {
+    /// Pushes any value, spelled out with digits and arithmetic if it isn't a
+    /// single digit.
+    Push(isize),
+    /// Emits the character as is.
+    Op(char),
+}
+
+/// Builds a one-line program executing the instructions from left to right.
+///
+/// ```
+/// use befunge93_rs::{assemble, Instruction::*};
+///
+/// assert_eq!(assemble(&[Push(2), Push(3), Op('+'), Op('@')]), "23+@");
+/// assert_eq!(assemble(&[Push(15)]), "35*");
+/// ```
+pub fn assemble(instructions: &[Instruction]) -> String {
+    let mut program = String::new();
+
+    for &instruction in instructions {
+        match instruction {
+            Instruction::Push(n) if n < 0 => {
+                program.push('0');
+                push_unsigned(&mut program, n.unsigned_abs());
+                program.push('-');
+            }
+            Instruction::Push(n) => push_unsigned(&mut program, n.unsigned_abs()),
+            Instruction::Op(c) => program.push(c),
+        }
+    }
+
+    program
+}
+
+/// Spells out `n` by factoring it into digits where possible and adding the
+/// remainder of a division by 9 otherwise.
+fn push_unsigned(program: &mut String, n: usize) {
+    if n <= 9 {
+        program.push(char::from_digit(n as u32, 10).expect("is digit"));
+        return;
+    }
+
+    if let Some(d) = (2..=9).rev().find(|&d| n.is_multiple_of(d)) {
+        push_unsigned(program, n / d);
+        push_unsigned(program, d);
+        program.push('*');
+        return;
+    }
+
+    push_unsigned(program, n - n % 9);
+    push_unsigned(program, n % 9);
+    program.push('+');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::build_interpreter;
+
+    fn run(program: &str) -> Vec<isize> {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program(program).unwrap();
+        interpreter.run().unwrap();
+
+        interpreter.get_stack().to_vec()
+    }
+
+    #[test]
+    fn test_assemble_digits_and_ops() {
+        let program = assemble(&[
+            Instruction::Push(2),
+            Instruction::Push(3),
+            Instruction::Op('+'),
+            Instruction::Op('@'),
+        ]);
+
+        assert_eq!(program, "23+@");
+    }
+
+    #[test]
+    fn test_assemble_push_15() {
+        let program = assemble(&[Instruction::Push(15), Instruction::Op('@')]);
+
+        assert_eq!(run(&program), [15]);
+    }
+
+    #[test]
+    fn test_assemble_large_and_negative_values() {
+        let values = [0, 9, 10, 97, 1000, 65536, -1, -123];
+        let mut instructions: Vec<_> = values.iter().map(|&n| Instruction::Push(n)).collect();
+        instructions.push(Instruction::Op('@'));
+
+        let program = assemble(&instructions);
+
+        assert_eq!(run(&program), values);
+    }
+}
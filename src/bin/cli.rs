@@ -1,30 +1,329 @@
 use befunge93_rs::*;
+use rand::rngs::ThreadRng;
 use std::process::exit;
 use std::{
     env,
     fs::File,
-    io::{BufReader, Read},
+    io::{self, BufReader, IsTerminal, Read, StdinLock, Stdout, Write},
+    thread,
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
 
-fn main() -> Result<()> {
-    let mut args = env::args();
-    if args.len() < 2 {
-        println!("Usage: befunge93-rs [PATH]");
+type CliInterpreter = Interpreter<StdinLock<'static>, Stdout, ThreadRng>;
+
+const USAGE: &str = "Usage: befunge93-rs [--newline] [--stats] [--validate] [--dump-on-halt] \
+                     [--quiet] [--grid-size WxH] [--animate] [--animate-delay MS] \
+                     [--analyze] [--verbose] [--step-limit N] [--profile] [--trace] \
+                     [--color auto|always|never] [--lint] [PATH]";
+
+const DEFAULT_ANIMATE_DELAY: Duration = Duration::from_millis(100);
+const PROFILE_CELLS: usize = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+struct Options {
+    path: String,
+    newline: bool,
+    stats: bool,
+    validate: bool,
+    dump_on_halt: bool,
+    quiet: bool,
+    grid_size: Option<(usize, usize)>,
+    animate: bool,
+    animate_delay: Duration,
+    analyze: bool,
+    verbose: bool,
+    step_limit: Option<u64>,
+    profile: bool,
+    trace: bool,
+    color: Color,
+    lint: bool,
+}
+
+fn parse_grid_size(s: &str) -> Option<(usize, usize)> {
+    let (width, height) = s.split_once('x')?;
+
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+fn parse_color(s: &str) -> Option<Color> {
+    match s {
+        "auto" => Some(Color::Auto),
+        "always" => Some(Color::Always),
+        "never" => Some(Color::Never),
+        _ => None,
+    }
+}
+
+/// ANSI color of the instruction category: arithmetic green, movement blue,
+/// I/O yellow and control red. Everything else is left plain.
+fn instruction_color(c: char) -> Option<&'static str> {
+    match c {
+        '+' | '-' | '*' | '/' | '%' | '!' | '`' => Some("32"),
+        '>' | '<' | '^' | 'v' | '?' | '_' | '|' | '#' => Some("34"),
+        '.' | ',' | '&' | '~' => Some("33"),
+        '@' => Some("31"),
+        _ => None,
+    }
+}
+
+/// Executes one instruction at a time, calling `on_step` after each, until
+/// the program halts or `step_limit` instructions were executed. Returns the
+/// number of executed instructions along with the result.
+fn step_through(
+    interpreter: &mut CliInterpreter,
+    step_limit: Option<u64>,
+    mut on_step: impl FnMut(&CliInterpreter, StepInfo),
+) -> (Result<(), InterpreterError>, u64) {
+    let mut steps = 0;
+
+    while interpreter.get_enabled() && step_limit.is_none_or(|limit| steps < limit) {
+        match interpreter.step_detailed() {
+            Ok(info) => on_step(interpreter, info),
+            Err(e) => return (Err(e), steps),
+        }
+        steps += 1;
+    }
+
+    (Ok(()), steps)
+}
+
+fn parse_args() -> Option<Options> {
+    let mut path = None;
+    let mut newline = false;
+    let mut stats = false;
+    let mut validate = false;
+    let mut dump_on_halt = false;
+    let mut quiet = false;
+    let mut grid_size = None;
+    let mut animate = false;
+    let mut animate_delay = DEFAULT_ANIMATE_DELAY;
+    let mut analyze = false;
+    let mut verbose = false;
+    let mut step_limit = None;
+    let mut profile = false;
+    let mut trace = false;
+    let mut color = Color::Auto;
+    let mut lint = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--newline" => newline = true,
+            "--stats" => stats = true,
+            "--validate" => validate = true,
+            "--dump-on-halt" => dump_on_halt = true,
+            "--quiet" => quiet = true,
+            "--grid-size" => grid_size = Some(parse_grid_size(&args.next()?)?),
+            "--animate" => animate = true,
+            "--analyze" => analyze = true,
+            "--verbose" => verbose = true,
+            "--profile" => profile = true,
+            "--trace" => trace = true,
+            "--lint" => lint = true,
+            "--color" => color = parse_color(&args.next()?)?,
+            "--step-limit" => step_limit = Some(args.next()?.parse().ok()?),
+            "--animate-delay" => animate_delay = Duration::from_millis(args.next()?.parse().ok()?),
+            _ if arg.starts_with("--") => return None,
+            _ if path.is_none() => path = Some(arg),
+            _ => return None,
+        }
+    }
+
+    Some(Options {
+        path: path?,
+        newline,
+        stats,
+        validate,
+        dump_on_halt,
+        quiet,
+        grid_size,
+        animate,
+        animate_delay,
+        analyze,
+        verbose,
+        step_limit,
+        profile,
+        trace,
+        color,
+        lint,
+    })
+}
+
+fn main() {
+    let options = match parse_args() {
+        Some(options) => options,
+        None => {
+            eprintln!("{}", USAGE);
+            exit(1);
+        }
+    };
+
+    if let Err(e) = run(&options) {
+        if !options.quiet {
+            eprintln!("Error: {:?}", e);
+        }
         exit(1);
     }
+}
 
-    let path = args.nth(1).expect("at least 2 arguments");
-    let file = File::open(path)?;
+fn run(options: &Options) -> Result<()> {
+    let file = File::open(&options.path)?;
     let mut buf_reader = BufReader::new(file);
     let mut program = String::new();
     buf_reader.read_to_string(&mut program)?;
 
-    let mut interpreter = Interpreter::default();
-    interpreter.load_program(&program)?;
+    let mut interpreter = Interpreter::from_source(&program)?;
+    if let Some((width, height)) = options.grid_size {
+        interpreter.resize_grid(width, height);
+    }
+    interpreter.set_collect_stats(options.stats && !options.quiet);
+    interpreter.set_step_limit(options.step_limit);
+    interpreter.set_profile(options.profile && !options.quiet);
+    interpreter.set_halt_on_broken_pipe(true);
+
+    if options.dump_on_halt && !options.quiet {
+        interpreter.set_halt_hook(|interpreter| {
+            let _ = io::stdout().flush();
+            eprintln!("Stack: {:?}", interpreter.get_stack());
+            eprintln!("Grid:\n{}", interpreter.render());
+        });
+    }
+
+    if options.analyze {
+        let stats = interpreter.analyze();
+        println!("instructions:");
+        for (c, count) in stats.instructions {
+            println!("  {:?}: {}", c, count);
+        }
+        println!("self-modifying: {}", stats.self_modifying);
+        println!("reads input: {}", stats.reads_input);
+        println!("halts: {}", stats.halts);
+
+        return Ok(());
+    }
+
+    if options.validate {
+        if let Err(invalid) = interpreter.validate_program() {
+            if options.quiet {
+                exit(1);
+            }
+            eprintln!("Program contains non-printable ASCII characters:");
+            for (x, y, c) in invalid {
+                eprintln!("  x: {}, y: {}, character: {:?}", x, y, c);
+            }
+            exit(1);
+        }
+    }
+
+    if options.lint
+        && !options.quiet
+        && interpreter.static_termination_check() == TerminationHint::NoHalt
+    {
+        eprintln!("warning: program has no `@` outside of strings and may loop forever");
+    }
+
+    let started = Instant::now();
+    let (result, stats) = if options.animate && !options.quiet {
+        // Moves the cursor home and clears what is left of the previous frame,
+        // so the grid is redrawn in place. Taller grids simply scroll.
+        let draw = |interpreter: &CliInterpreter| {
+            let _ = io::stdout().flush();
+            eprint!("\x1b[H{}\n\x1b[J", interpreter.render());
+        };
+
+        eprint!("\x1b[2J");
+        draw(&interpreter);
+        let (result, steps) =
+            step_through(&mut interpreter, options.step_limit, |interpreter, _| {
+                thread::sleep(options.animate_delay);
+                draw(interpreter);
+            });
+        let elapsed = started.elapsed();
+
+        (result, Some(RunStats { steps, elapsed }))
+    } else if options.trace && !options.quiet {
+        let color = match options.color {
+            Color::Auto => io::stderr().is_terminal(),
+            Color::Always => true,
+            Color::Never => false,
+        };
 
-    interpreter.run()?;
+        let (result, steps) = step_through(&mut interpreter, options.step_limit, |_, info| {
+            let (x, y) = info.pc_before;
+            let _ = io::stdout().flush();
+            match instruction_color(info.instruction) {
+                Some(code) if color => {
+                    eprintln!("({}, {}) \x1b[{}m{}\x1b[0m", x, y, code, info.instruction)
+                }
+                _ => eprintln!("({}, {}) {}", x, y, info.instruction),
+            }
+        });
+        let elapsed = started.elapsed();
+
+        (result, Some(RunStats { steps, elapsed }))
+    } else {
+        let result = interpreter.run();
+
+        (result, interpreter.last_run_stats())
+    };
+
+    if options.verbose && !options.quiet {
+        let reason = match result {
+            Err(_) => HaltReason::Errored,
+            Ok(()) if interpreter.get_enabled() => HaltReason::StepLimit,
+            Ok(()) => HaltReason::Halted,
+        };
+        let _ = io::stdout().flush();
+        let (x, y) = interpreter.pc();
+        eprintln!("halt reason: {:?}, pc: ({}, {})", reason, x, y);
+    }
+
+    if let Some(stats) = stats.filter(|_| options.stats && !options.quiet) {
+        let seconds = stats.elapsed.as_secs_f64();
+        let per_second = if seconds > 0.0 {
+            stats.steps as f64 / seconds
+        } else {
+            0.0
+        };
+        eprintln!(
+            "steps: {}, elapsed: {:?}, instructions per second: {:.0}",
+            stats.steps, stats.elapsed, per_second
+        );
+    }
+
+    let hotspots = interpreter.cell_hotspots();
+    if !hotspots.is_empty() {
+        eprintln!("Hottest cells:");
+        for ((x, y), count) in hotspots.into_iter().take(PROFILE_CELLS) {
+            let c = interpreter.get_cell(x, y).unwrap_or(' ');
+            eprintln!(
+                "  x: {}, y: {}, instruction: {:?}, count: {}",
+                x, y, c, count
+            );
+        }
+    }
+
+    result?;
+
+    let mut stdout = io::stdout();
+    let finished = match options.newline && !interpreter.get_enabled() {
+        true => stdout.write_all(b"\n"),
+        false => Ok(()),
+    }
+    .and_then(|_| stdout.flush());
+
+    match finished {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+        finished => finished?,
+    }
 
     Ok(())
 }
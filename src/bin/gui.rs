@@ -60,6 +60,7 @@ struct Befunge93App {
     running: bool,
     is_error_window_open: bool,
     error_message: String,
+    warning: Option<&'static str>,
 }
 
 impl Befunge93App {
@@ -76,6 +77,7 @@ impl Befunge93App {
         let running = false;
         let is_error_window_open = false;
         let error_message = String::new();
+        let warning = None;
 
         Befunge93App {
             program,
@@ -83,6 +85,7 @@ impl Befunge93App {
             running,
             is_error_window_open,
             error_message,
+            warning,
         }
     }
 }
@@ -136,9 +139,12 @@ impl eframe::App for Befunge93App {
             ui.horizontal(|ui| {
                 if ui.button("Load program").clicked() {
                     let result = self.interpreter.load_program(&self.program);
+                    self.warning = None;
                     if let Err(inner) = result {
                         self.error_message = format!("{}", inner);
                         self.is_error_window_open = true;
+                    } else if self.interpreter.halt_cells().is_empty() {
+                        self.warning = Some("Program contains no `@` and may never halt");
                     }
                     self.interpreter.set_output(Cursor::new(Vec::new()));
                 }
@@ -164,6 +170,10 @@ impl eframe::App for Befunge93App {
                 } else {
                     ui.label("Not running");
                 }
+
+                if let Some(warning) = self.warning {
+                    ui.colored_label(egui::Color32::YELLOW, warning);
+                }
             });
         });
 
@@ -173,9 +183,7 @@ impl eframe::App for Befunge93App {
                 ui.vertical_centered(|ui| {
                     ui.heading("Output");
                 });
-                ui.label(String::from_utf8_lossy(
-                    self.interpreter.get_output().get_ref(),
-                ));
+                ui.label(String::from_utf8_lossy(self.interpreter.output_bytes()));
             });
 
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -1,21 +1,54 @@
 use std::{
-    io::{self, BufRead, StdinLock, Stdout, Write},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt,
+    io::{self, BufRead, Cursor, StdinLock, Stdout, Write},
     num::ParseIntError,
+    rc::Rc,
+    time::Duration,
 };
 use thiserror::Error;
 
+mod assembler;
+#[cfg(feature = "image")]
+mod png;
+mod reader;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use assembler::{assemble, Instruction};
+pub use reader::{FnReader, QueueReader};
+
 type Program = Vec<Vec<char>>;
 type ProgramCounter = (usize, usize);
-use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
 
-#[derive(Debug, Copy, Clone)]
-enum Direction {
+const MAX_WIDTH: usize = 80;
+const MAX_HEIGHT: usize = 25;
+const QUINE_STEP_LIMIT: usize = 1_000_000;
+use rand::{
+    rngs::{StdRng, ThreadRng},
+    seq::SliceRandom,
+    Rng,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Direction {
     Left,
     Right,
     Up,
     Down,
 }
 
+impl Direction {
+    pub fn reverse(self) -> Direction {
+        match self {
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+        }
+    }
+}
+
 const DIRECTIONS: [Direction; 4] = [
     Direction::Left,
     Direction::Right,
@@ -23,42 +56,385 @@ const DIRECTIONS: [Direction; 4] = [
     Direction::Down,
 ];
 
-#[derive(Debug, PartialEq)]
-enum Mode {
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RandomMode {
+    Rng,
+    RoundRobin,
+}
+
+/// What happens when the PC moves past an edge of the playfield.
+/// `WrapMode::Torus` re-enters on the opposite edge as Befunge-93 specifies,
+/// `WrapMode::Halt` stops the program instead.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WrapMode {
+    Torus,
+    Halt,
+}
+
+/// What loading does with programs larger than the 80x25 Befunge-93
+/// playfield. `OversizeBehavior::Allow` keeps them whole and is the default.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OversizeBehavior {
+    Error,
+    Truncate,
+    Allow,
+}
+
+/// What `/` and `%` do when the divisor is zero. Befunge-93 leaves this
+/// undefined; `DivByZero::Zero` pushes 0 and is the default.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DivByZero {
+    Zero,
+    Error,
+}
+
+/// What `.` and `,` do once the output limit set with
+/// [`Interpreter::set_output_limit`] is reached.
+/// `OutputLimitBehavior::Error` fails with
+/// `InterpreterError::OutputLimitExceeded` and is the default.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OutputLimitBehavior {
+    Error,
+    Halt,
+}
+
+/// How `~` reads a character. `InputMode::Bytes` pushes a single byte and is
+/// the default; `InputMode::Utf8` decodes one UTF-8 encoded character and
+/// pushes its code point.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputMode {
+    Bytes,
+    Utf8,
+}
+
+/// What string mode pushes for characters outside of ASCII.
+/// `StringMode::Byte` pushes the lowest 8 bits of the code point and is the
+/// default, `StringMode::Unicode` pushes the whole code point and
+/// `StringMode::Ascii` fails with `InterpreterError::NonAsciiInString`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StringMode {
+    Byte,
+    Unicode,
+    Ascii,
+}
+
+/// In `Mode::String` every cell up to the closing `"` is pushed, spaces
+/// included. Unlike Befunge-98, consecutive spaces are not collapsed into one.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
     Normal,
     String,
 }
 
+/// Holds the values stack instructions work on, see
+/// [`Interpreter::with_storage`]. `values` lists them in the order they were
+/// pushed.
+pub trait Storage: fmt::Debug + StorageClone {
+    fn push(&mut self, v: isize);
+
+    /// Removes the next value, returning 0 if there is none.
+    fn pop(&mut self) -> isize;
+
+    fn values(&self) -> &[isize];
+
+    /// Keeps the first `len` of `values`.
+    fn truncate(&mut self, len: usize);
+
+    /// Returns the index in `values` of the value `pop` removes next.
+    fn next_index(&self) -> usize {
+        self.values().len().saturating_sub(1)
+    }
+}
+
+/// Lets [`Interpreter::fork`] copy a [`Storage`]. Implemented for every
+/// storage that is `Clone`.
+pub trait StorageClone {
+    fn clone_box(&self) -> Box<dyn Storage>;
+}
+
+impl<S: Storage + Clone + 'static> StorageClone for S {
+    fn clone_box(&self) -> Box<dyn Storage> {
+        Box::new(self.clone())
+    }
+}
+
+/// The Befunge-93 storage, popping the value pushed last.
+#[derive(Debug, Clone, Default)]
+pub struct Stack {
+    values: Vec<isize>,
+}
+
+impl Storage for Stack {
+    fn push(&mut self, v: isize) {
+        self.values.push(v);
+    }
+
+    fn pop(&mut self) -> isize {
+        self.values.pop().unwrap_or(0)
+    }
+
+    fn values(&self) -> &[isize] {
+        &self.values
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.values.truncate(len);
+    }
+}
+
+/// Pops values in the order they were pushed. This is not part of
+/// Befunge-93 and is meant for experimenting with variants.
+#[derive(Debug, Clone, Default)]
+pub struct Queue {
+    values: Vec<isize>,
+    front: usize,
+}
+
+impl Storage for Queue {
+    fn push(&mut self, v: isize) {
+        self.values.push(v);
+    }
+
+    fn pop(&mut self) -> isize {
+        let Some(&v) = self.values.get(self.front) else {
+            return 0;
+        };
+
+        // Drops popped values once they make up half of the buffer, so every
+        // value is moved at most once on average.
+        self.front += 1;
+        if self.front * 2 >= self.values.len() {
+            self.values.drain(..self.front);
+            self.front = 0;
+        }
+
+        v
+    }
+
+    fn values(&self) -> &[isize] {
+        &self.values[self.front..]
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.values.truncate(self.front + len);
+    }
+
+    fn next_index(&self) -> usize {
+        0
+    }
+}
+
+#[derive(Debug)]
+struct Operands {
+    inner: Box<dyn Storage>,
+    default: isize,
+    limit: Option<usize>,
+    journal: Option<StackJournal<isize>>,
+    hook: Option<Hook<StackHook>>,
+}
+
+/// A value entering or leaving the stack, see [`Interpreter::set_stack_hook`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StackEvent<T = isize> {
+    Push(T),
+    Pop(T),
+}
+
+/// Input consumed by `&` or `~`, see [`Interpreter::set_input_hook`].
+/// `bytes` holds everything read, including whitespace skipped by `&`, and
+/// `pc` the `(x, y)` coordinates of the instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputEvent {
+    pub pc: (usize, usize),
+    pub bytes: Vec<u8>,
+    pub value: isize,
+}
+
 #[derive(Debug)]
-struct Stack<T: Copy> {
-    inner: Vec<T>,
-    default: T,
+struct StackJournal<T> {
+    low_water: usize,
+    removed: Vec<T>,
 }
 
-impl<T: Copy> Stack<T> {
-    fn new(default: T) -> Self {
-        let inner = Vec::new();
+impl Operands {
+    fn new(default: isize, inner: Box<dyn Storage>) -> Self {
+        let limit = None;
+        let journal = None;
+        let hook = None;
+
+        Operands {
+            inner,
+            default,
+            limit,
+            journal,
+            hook,
+        }
+    }
+
+    fn values(&self) -> &[isize] {
+        self.inner.values()
+    }
 
-        Stack { inner, default }
+    fn pop(&mut self) -> isize {
+        self.pop_checked().unwrap_or(self.default)
     }
 
-    fn pop(&mut self) -> T {
-        self.inner.pop().unwrap_or(self.default)
+    fn pop_checked(&mut self) -> Option<isize> {
+        if self.values().is_empty() {
+            return None;
+        }
+
+        self.touch(self.inner.next_index());
+        let value = self.inner.pop();
+        self.emit(StackEvent::Pop(value));
+
+        Some(value)
     }
 
-    fn pop2(&mut self) -> (T, T) {
+    fn pop2(&mut self) -> (isize, isize) {
         (self.pop(), self.pop())
     }
 
-    fn push(&mut self, value: T) {
+    /// Fails if `additional` more values would exceed the limit.
+    fn reserve(&self, additional: usize) -> InterpreterResult<()> {
+        match self.limit {
+            Some(limit) if self.values().len() + additional > limit => {
+                Err(InterpreterError::StackOverflow { limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn push(&mut self, value: isize) -> InterpreterResult<()> {
+        self.reserve(1)?;
+        self.push_unchecked(value);
+
+        Ok(())
+    }
+
+    fn push_unchecked(&mut self, value: isize) {
         self.inner.push(value);
+        self.emit(StackEvent::Push(value));
+    }
+
+    /// Replaces every value, e.g. to seed the stack, without firing events.
+    fn set_values(&mut self, values: &[isize]) {
+        self.inner.truncate(0);
+        for &value in values {
+            self.inner.push(value);
+        }
+    }
+
+    fn dup_top(&mut self) -> InterpreterResult<()> {
+        self.reserve(if self.values().is_empty() { 2 } else { 1 })?;
+
+        let n = self.pop();
+        self.push_unchecked(n);
+        self.push_unchecked(n);
+
+        Ok(())
+    }
+
+    fn swap_top(&mut self) -> InterpreterResult<()> {
+        self.reserve(2usize.saturating_sub(self.values().len()))?;
+
+        let (a, b) = self.pop2();
+        self.push_unchecked(a);
+        self.push_unchecked(b);
+
+        Ok(())
+    }
+
+    fn emit(&mut self, event: StackEvent) {
+        if let Some(Hook(hook)) = &mut self.hook {
+            hook(event);
+        }
+    }
+
+    fn touch(&mut self, index: usize) {
+        if let Some(journal) = &mut self.journal {
+            while journal.low_water > index {
+                journal.low_water -= 1;
+                journal.removed.push(self.inner.values()[journal.low_water]);
+            }
+        }
+    }
+
+    fn begin_journal(&mut self) {
+        let low_water = self.values().len();
+        let removed = Vec::new();
+
+        self.journal = Some(StackJournal { low_water, removed });
+    }
+
+    fn end_journal(&mut self) -> Option<StackJournal<isize>> {
+        self.journal.take()
+    }
+
+    fn revert(&mut self, journal: StackJournal<isize>) {
+        self.inner.truncate(journal.low_water);
+        for value in journal.removed.into_iter().rev() {
+            self.inner.push(value);
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Choice {
+    Direction(Direction),
+    Input(isize),
+}
+
+#[derive(Debug)]
+struct Delta {
+    pc: ProgramCounter,
+    direction: Direction,
+    mode: Mode,
+    enabled: bool,
+    stack: StackJournal<isize>,
+    cell: Option<(ProgramCounter, char)>,
+    choice: Option<Choice>,
+}
+
+#[derive(Debug, Default)]
+struct History {
+    limit: usize,
+    deltas: VecDeque<Delta>,
+    replay: Vec<Option<Choice>>,
+    cell: Option<(ProgramCounter, char)>,
+    choice: Option<Choice>,
+}
+
+#[derive(Debug, Clone)]
+struct StackWatch {
+    depth: usize,
+    value: Option<isize>,
+    changed: bool,
+}
+
+struct Hook<F: ?Sized>(Box<F>);
+
+impl<F: ?Sized> fmt::Debug for Hook<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Hook")
     }
 }
 
+type WrapHook = dyn FnMut((usize, usize), (usize, usize), Direction);
+type StackHook = dyn FnMut(StackEvent);
+type InputHook = dyn FnMut(InputEvent);
+type HaltHook<R, W, G> = dyn FnOnce(&Interpreter<R, W, G>);
+type Handler<R, W, G> = fn(&mut Interpreter<R, W, G>) -> InterpreterResult<()>;
+type FastRuns<R, W, G> = HashMap<(ProgramCounter, Direction), Rc<[Handler<R, W, G>]>>;
+
 #[derive(Debug)]
 pub struct Interpreter<R: BufRead, W: Write, G: Rng> {
-    stack: Stack<isize>,
+    stack: Operands,
+    initial_stack: Vec<isize>,
+    /// Always `height` rows of exactly `width` cells, see
+    /// [`Interpreter::validate_grid`].
     program: Program,
+    original_program: Program,
     pc: ProgramCounter,
     direction: Direction,
     width: usize,
@@ -68,6 +444,44 @@ pub struct Interpreter<R: BufRead, W: Write, G: Rng> {
     output: W,
     gen: G,
     enabled: bool,
+    history: History,
+    code_cells: HashSet<(usize, usize)>,
+    self_modifications: Vec<(usize, usize)>,
+    wrap_hook: Option<Hook<WrapHook>>,
+    random_mode: RandomMode,
+    random_index: usize,
+    div_by_zero: DivByZero,
+    start: (ProgramCounter, Direction),
+    separator: Option<u8>,
+    reference_compat: bool,
+    track_coverage: bool,
+    visited_cells: HashSet<(usize, usize)>,
+    echo_input: bool,
+    collect_stats: bool,
+    last_run_stats: Option<RunStats>,
+    halt_hook: Option<Hook<HaltHook<R, W, G>>>,
+    input_hook: Option<Hook<InputHook>>,
+    comment_prefix: Option<String>,
+    wrap_mode: WrapMode,
+    oversize_behavior: OversizeBehavior,
+    fast_path: bool,
+    fast_runs: FastRuns<R, W, G>,
+    fast_cells: HashSet<(usize, usize)>,
+    stack_watches: Vec<StackWatch>,
+    input_mode: InputMode,
+    string_mode: StringMode,
+    step_limit: Option<u64>,
+    last_halt_reason: Option<HaltReason>,
+    profile: bool,
+    cell_counts: HashMap<(usize, usize), u64>,
+    halt_on_broken_pipe: bool,
+    output_limit: Option<usize>,
+    output_limit_behavior: OutputLimitBehavior,
+    output_written: usize,
+    input_limit: Option<usize>,
+    input_reads: usize,
+    recording: Option<Recording>,
+    scripted_directions: VecDeque<Direction>,
 }
 
 #[derive(Error, Debug)]
@@ -82,24 +496,204 @@ pub enum InterpreterError {
     InvalidCoordinates { x: isize, y: isize },
     #[error("parse int error encountered")]
     ParseError(#[from] ParseIntError),
+    #[error("no program loaded")]
+    ProgramNotLoaded,
+    #[error("tried to divide `{x}` by zero")]
+    DivisionByZero { x: isize, y: isize },
+    #[error("program of size {width}x{height} exceeds 80x25")]
+    ProgramTooLarge { width: usize, height: usize },
+    #[error("stack exceeded the limit of {limit} values")]
+    StackOverflow { limit: usize },
+    #[error("non-ASCII character `{character}` in string at ({x}, {y})")]
+    NonAsciiInString { x: usize, y: usize, character: char },
+    #[error("output exceeded the limit of {limit} bytes")]
+    OutputLimitExceeded { limit: usize },
+}
+
+impl InterpreterError {
+    /// Returns a stable identifier of the error kind, e.g. for reporting
+    /// errors outside of Rust without parsing the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            InterpreterError::IoError(_) => "io_error",
+            InterpreterError::UnknownInstruction(_) => "unknown_instruction",
+            InterpreterError::InvalidAscii(_) => "invalid_ascii",
+            InterpreterError::InvalidCoordinates { .. } => "invalid_coordinates",
+            InterpreterError::ParseError(_) => "parse_error",
+            InterpreterError::ProgramNotLoaded => "program_not_loaded",
+            InterpreterError::DivisionByZero { .. } => "division_by_zero",
+            InterpreterError::ProgramTooLarge { .. } => "program_too_large",
+            InterpreterError::StackOverflow { .. } => "stack_overflow",
+            InterpreterError::NonAsciiInString { .. } => "non_ascii_in_string",
+            InterpreterError::OutputLimitExceeded { .. } => "output_limit_exceeded",
+        }
+    }
 }
 
 type InterpreterResult<T> = Result<T, InterpreterError>;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StepOutcome {
+    Running,
+    Halted,
+}
+
+/// Describes a single executed step. Coordinates are `(x, y)`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StepInfo {
+    pub instruction: char,
+    pub pc_before: (usize, usize),
+    pub pc_after: (usize, usize),
+    pub outcome: StepOutcome,
+}
+
+/// Why the last [`Interpreter::run`] returned.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HaltReason {
+    Halted,
+    StepLimit,
+    Errored,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RunStats {
+    pub steps: u64,
+    pub elapsed: Duration,
+}
+
+/// Measures wall time of a run. `std::time::Instant` is unavailable on
+/// `wasm32-unknown-unknown`, where the elapsed time is always zero.
+struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+}
+
+impl Stopwatch {
+    fn start() -> Self {
+        Stopwatch {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.start.elapsed();
+
+        #[cfg(target_arch = "wasm32")]
+        return Duration::ZERO;
+    }
+}
+
+/// Everything needed to reproduce a run of a program: its source, the input
+/// it consumed and the directions `?` took, see [`Interpreter::record`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub source: String,
+    pub input: Vec<u8>,
+    pub directions: Vec<Direction>,
+}
+
+/// Whether a loaded grid has a way to halt, see
+/// [`Interpreter::static_termination_check`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TerminationHint {
+    HasHalt,
+    NoHalt,
+}
+
+/// Static summary of a loaded grid, see [`Interpreter::analyze`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgramStats {
+    /// Number of occurrences of every character other than space.
+    pub instructions: BTreeMap<char, usize>,
+    /// Contains `p` or `g`.
+    pub self_modifying: bool,
+    /// Contains `&` or `~`.
+    pub reads_input: bool,
+    /// Contains `@`.
+    pub halts: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunResult {
+    pub output: Vec<u8>,
+    pub stack: Vec<isize>,
+    pub halted: bool,
+}
+
 impl<R: BufRead, W: Write, G: Rng> Interpreter<R, W, G> {
     pub fn new(input: R, output: W, gen: G) -> Self {
-        let stack = Stack::new(0);
+        Self::with_storage(input, output, gen, Stack::default())
+    }
+
+    /// Creates an interpreter whose stack starts out holding `initial`, bottom
+    /// to top, instead of being empty. Loading a program seeds the stack again.
+    pub fn with_stack(input: R, output: W, gen: G, initial: Vec<isize>) -> Self {
+        let mut interpreter = Self::new(input, output, gen);
+        interpreter.stack.set_values(&initial);
+        interpreter.initial_stack = initial;
+
+        interpreter
+    }
+
+    /// Creates an interpreter whose stack instructions operate on the given
+    /// storage, e.g. a [`Queue`] instead of the usual [`Stack`].
+    pub fn with_storage(input: R, output: W, gen: G, storage: impl Storage + 'static) -> Self {
+        let stack = Operands::new(0, Box::new(storage));
+        let initial_stack = Vec::new();
         let program = Vec::new();
+        let original_program = Vec::new();
         let pc = (0, 0);
         let direction = Direction::Right;
         let width = 0;
         let height = 0;
         let mode = Mode::Normal;
         let enabled = false;
+        let history = History::default();
+        let code_cells = HashSet::new();
+        let self_modifications = Vec::new();
+        let wrap_hook = None;
+        let random_mode = RandomMode::Rng;
+        let random_index = 0;
+        let div_by_zero = DivByZero::Zero;
+        let start = ((0, 0), Direction::Right);
+        let separator = None;
+        let reference_compat = false;
+        let track_coverage = false;
+        let visited_cells = HashSet::new();
+        let echo_input = false;
+        let collect_stats = false;
+        let last_run_stats = None;
+        let halt_hook = None;
+        let input_hook = None;
+        let comment_prefix = None;
+        let wrap_mode = WrapMode::Torus;
+        let oversize_behavior = OversizeBehavior::Allow;
+        let fast_path = false;
+        let fast_runs = HashMap::new();
+        let fast_cells = HashSet::new();
+        let stack_watches = Vec::new();
+        let input_mode = InputMode::Bytes;
+        let string_mode = StringMode::Byte;
+        let step_limit = None;
+        let last_halt_reason = None;
+        let profile = false;
+        let cell_counts = HashMap::new();
+        let halt_on_broken_pipe = false;
+        let output_limit = None;
+        let output_limit_behavior = OutputLimitBehavior::Error;
+        let output_written = 0;
+        let input_limit = None;
+        let input_reads = 0;
+        let recording = None;
+        let scripted_directions = VecDeque::new();
 
         Interpreter {
             stack,
+            initial_stack,
             program,
+            original_program,
             pc,
             direction,
             width,
@@ -109,6 +703,44 @@ impl<R: BufRead, W: Write, G: Rng> Interpreter<R, W, G> {
             output,
             gen,
             enabled,
+            history,
+            code_cells,
+            self_modifications,
+            wrap_hook,
+            random_mode,
+            random_index,
+            div_by_zero,
+            start,
+            separator,
+            reference_compat,
+            track_coverage,
+            visited_cells,
+            echo_input,
+            collect_stats,
+            last_run_stats,
+            halt_hook,
+            input_hook,
+            comment_prefix,
+            wrap_mode,
+            oversize_behavior,
+            fast_path,
+            fast_runs,
+            fast_cells,
+            stack_watches,
+            input_mode,
+            string_mode,
+            step_limit,
+            last_halt_reason,
+            profile,
+            cell_counts,
+            halt_on_broken_pipe,
+            output_limit,
+            output_limit_behavior,
+            output_written,
+            input_limit,
+            input_reads,
+            recording,
+            scripted_directions,
         }
     }
 
@@ -125,817 +757,4232 @@ impl<R: BufRead, W: Write, G: Rng> Interpreter<R, W, G> {
     }
 
     pub fn get_stack(&self) -> &[isize] {
-        &self.stack.inner
+        self.stack.values()
     }
 
     pub fn get_enabled(&self) -> bool {
         self.enabled
     }
 
-    pub fn set_output(&mut self, output: W) {
-        self.output = output;
+    pub fn mode(&self) -> Mode {
+        self.mode
     }
 
-    pub fn load_program(&mut self, program: &str) -> InterpreterResult<()> {
-        let longest_line_len = program.lines().map(|line| line.len()).max().unwrap_or(0);
-        let rows_len = program.lines().count();
-
-        self.program = vec![vec![' '; longest_line_len]; rows_len];
-
-        for (i, line) in program.lines().enumerate() {
-            for (j, c) in line.chars().enumerate() {
-                self.program[i][j] = c;
-            }
+    /// Guesses the mode from the `"` cells the PC has passed on its way from
+    /// the edge of the playfield, and returns the guess if it disagrees with
+    /// the current mode. Meant for debugging starting points away from the
+    /// origin; strings spanning wraps or turns fool it.
+    pub fn mode_at_pc_heuristic(&self) -> Option<Mode> {
+        if !self.is_loaded() {
+            return None;
         }
 
-        self.stack = Stack::new(0);
-        self.pc = (0, 0);
-        self.direction = Direction::Right;
-        self.width = longest_line_len;
-        self.height = rows_len;
-        self.mode = Mode::Normal;
-        self.enabled = true;
+        let (i, j) = self.pc;
+        let row = &self.program[i];
+        let column = self.program.iter().map(|line| line[j]);
+        let quotes = match self.direction {
+            Direction::Right => row[..j].iter().filter(|&&c| c == '"').count(),
+            Direction::Left => row[j + 1..].iter().filter(|&&c| c == '"').count(),
+            Direction::Down => column.take(i).filter(|&c| c == '"').count(),
+            Direction::Up => column.skip(i + 1).filter(|&c| c == '"').count(),
+        };
 
-        Ok(())
+        let guess = if quotes % 2 == 1 {
+            Mode::String
+        } else {
+            Mode::Normal
+        };
+
+        (guess != self.mode).then_some(guess)
     }
 
-    pub fn run(&mut self) -> InterpreterResult<()> {
-        if self.program.is_empty() {
-            return Ok(());
-        }
+    /// Returns the `(x, y)` coordinates of the PC.
+    pub fn pc(&self) -> (usize, usize) {
+        (self.pc.1, self.pc.0)
+    }
 
-        while self.enabled {
-            self.step()?;
-        }
+    /// Returns the character at `(x, y)`, or `None` outside of the grid.
+    pub fn get_cell(&self, x: usize, y: usize) -> Option<char> {
+        self.program.get(y)?.get(x).copied()
+    }
 
-        Ok(())
+    /// Returns the character under the PC, or `None` if no program is loaded.
+    pub fn current_instruction(&self) -> Option<char> {
+        self.is_loaded().then(|| self.get_instruction())
     }
 
-    pub fn step(&mut self) -> InterpreterResult<()> {
-        if self.program.is_empty() || !self.enabled {
-            return Ok(());
-        }
+    pub fn set_output(&mut self, output: W) {
+        self.output = output;
+    }
 
-        let instruction = self.get_instruction();
+    /// Replaces the random number generator used by `?`. To switch between
+    /// generators of different types, e.g. a seeded `StdRng` and
+    /// `ThreadRng`, build the interpreter with `G = Box<dyn RngCore>`. Every
+    /// `?` then goes through dynamic dispatch, which is slightly slower.
+    pub fn set_rng(&mut self, gen: G) {
+        self.gen = gen;
+    }
 
-        if self.mode == Mode::String {
-            if instruction == '"' {
-                self.toggle_string_mode()?;
-            } else {
-                self.stack.push((instruction as u8).into());
-            }
-        } else {
-            match instruction {
-                '+' => self.add()?,
-                '-' => self.subtract()?,
-                '*' => self.multiply()?,
-                '/' => self.divide()?,
-                '%' => self.remainder()?,
-                '!' => self.logical_not()?,
-                '`' => self.greater_than()?,
-                '>' => self.start_moving_right()?,
-                '<' => self.start_moving_left()?,
-                '^' => self.start_moving_up()?,
-                'v' => self.start_moving_down()?,
-                '?' => self.start_moving_randomly()?,
-                '_' => self.horizontal_if()?,
-                '|' => self.vertical_if()?,
-                '"' => self.toggle_string_mode()?,
-                ':' => self.duplicate_top_of_the_stack()?,
-                '\\' => self.swap_top_stack_values()?,
-                '$' => self.pop_and_discard()?,
-                '.' => self.pop_and_output_int()?,
-                ',' => self.pop_and_output_char()?,
-                '#' => self.bridge()?,
-                'p' => self.put()?,
-                'g' => self.get()?,
-                '&' => self.get_int_and_push()?,
-                '~' => self.get_char_and_push()?,
-                ' ' => (),
-                '@' => self.enabled = false,
-                _ if instruction.is_ascii_digit() => self.push_digit_to_stack()?,
-                i => return Err(InterpreterError::UnknownInstruction(i)),
-            };
-        }
+    pub fn into_parts(self) -> (R, W, G) {
+        (self.input, self.output, self.gen)
+    }
 
-        self.move_pc();
+    /// Enables recording of up to `limit` steps that can be undone with
+    /// [`Interpreter::step_back`]. A limit of 0 disables the history.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.limit = limit;
 
-        Ok(())
+        while self.history.deltas.len() > limit {
+            self.history.deltas.pop_front();
+        }
     }
 
-    fn pop_ascii(&mut self) -> InterpreterResult<char> {
-        let v__ = self.stack.pop();
-        let v_: u8 = v__
-            .try_into()
-            .or(Err(InterpreterError::InvalidAscii(v__)))?;
-        let v = v_ as char;
+    pub fn get_history_len(&self) -> usize {
+        self.history.deltas.len()
+    }
 
-        Ok(v)
+    /// Watches the value `depth` positions below the top of the stack, see
+    /// [`Interpreter::watch_changed`].
+    pub fn add_stack_watch(&mut self, depth: usize) {
+        let value = self.stack_value_at_depth(depth);
+        self.stack_watches.push(StackWatch {
+            depth,
+            value,
+            changed: false,
+        });
     }
 
-    fn get_instruction(&self) -> char {
-        let (i, j) = self.pc;
-        self.program[i][j]
+    /// Returns whether the value at any watched depth changed during the
+    /// last step. A watched position appearing or disappearing because the
+    /// stack grew or shrank counts as a change.
+    pub fn watch_changed(&self) -> bool {
+        self.stack_watches.iter().any(|watch| watch.changed)
     }
 
-    fn move_pc(&mut self) {
-        let (i, j) = &mut self.pc;
+    /// Selects how `?` picks a direction. `RandomMode::RoundRobin` cycles
+    /// through left, right, up and down instead of consulting the RNG.
+    pub fn set_random_mode(&mut self, random_mode: RandomMode) {
+        self.random_mode = random_mode;
+        self.random_index = 0;
+    }
 
-        match self.direction {
-            Direction::Left => *j = if *j == 0 { self.width - 1 } else { *j - 1 },
-            Direction::Right => *j = (*j + 1) % self.width,
-            Direction::Up => *i = if *i == 0 { self.height - 1 } else { *i - 1 },
-            Direction::Down => *i = (*i + 1) % self.height,
+    /// Sets the cell at `(x, y)` and direction the PC starts from, both now and
+    /// whenever a program is loaded. Fails with
+    /// `InterpreterError::InvalidCoordinates` if a program is loaded and the
+    /// cell lies outside of it. The mode is not inferred, so starting inside
+    /// string text runs it as code, see [`Interpreter::mode_at_pc_heuristic`].
+    pub fn set_start(&mut self, x: usize, y: usize, direction: Direction) -> InterpreterResult<()> {
+        if self.is_loaded() {
+            Self::check_start(x, y, self.width, self.height)?;
+            self.pc = (y, x);
+            self.direction = direction;
         }
-    }
 
-    fn push_digit_to_stack(&mut self) -> InterpreterResult<()> {
-        let instruction = self.get_instruction();
-        let n = char::to_digit(instruction, 10).expect("is digit") as isize;
-        self.stack.push(n);
+        self.start = ((y, x), direction);
 
         Ok(())
     }
 
-    fn add(&mut self) -> InterpreterResult<()> {
-        let (a, b) = self.stack.pop2();
-        self.stack.push(a + b);
+    fn check_start(x: usize, y: usize, width: usize, height: usize) -> InterpreterResult<()> {
+        if x >= width || y >= height {
+            return Err(InterpreterError::InvalidCoordinates {
+                x: x as isize,
+                y: y as isize,
+            });
+        }
 
         Ok(())
     }
 
-    fn subtract(&mut self) -> InterpreterResult<()> {
-        let (a, b) = self.stack.pop2();
-        self.stack.push(b - a);
+    /// Enables measuring the number of steps and time taken by
+    /// [`Interpreter::run`], see [`Interpreter::last_run_stats`].
+    pub fn set_collect_stats(&mut self, collect_stats: bool) {
+        self.collect_stats = collect_stats;
+    }
 
-        Ok(())
+    pub fn last_run_stats(&self) -> Option<RunStats> {
+        self.last_run_stats
     }
 
-    fn multiply(&mut self) -> InterpreterResult<()> {
-        let (a, b) = self.stack.pop2();
-        self.stack.push(a * b);
+    /// Makes [`Interpreter::run`] return after executing `max` steps even if
+    /// the program is still running. `None` removes the limit.
+    pub fn set_step_limit(&mut self, max: Option<u64>) {
+        self.step_limit = max;
+    }
 
-        Ok(())
+    pub fn last_halt_reason(&self) -> Option<HaltReason> {
+        self.last_halt_reason
     }
 
-    fn divide(&mut self) -> InterpreterResult<()> {
-        let (a, b) = self.stack.pop2();
-        let n = if a == 0 { 0 } else { b / a };
-        self.stack.push(n);
+    /// Makes `.` and `,` halt the program instead of failing with
+    /// `InterpreterError::IoError` when the output is a closed pipe, like
+    /// command line tools piped into `head`.
+    pub fn set_halt_on_broken_pipe(&mut self, halt_on_broken_pipe: bool) {
+        self.halt_on_broken_pipe = halt_on_broken_pipe;
+    }
 
-        Ok(())
+    /// Limits how many bytes `.` and `,` may write in one run of the program.
+    /// An output that does not fit is not written at all, see
+    /// [`OutputLimitBehavior`] for what happens instead. Unlimited by default.
+    pub fn set_output_limit(&mut self, max_bytes: usize) {
+        self.output_limit = Some(max_bytes);
     }
 
-    fn remainder(&mut self) -> InterpreterResult<()> {
-        let (a, b) = self.stack.pop2();
-        let n = if a == 0 { 0 } else { b % a };
-        self.stack.push(n);
+    pub fn set_output_limit_behavior(&mut self, output_limit_behavior: OutputLimitBehavior) {
+        self.output_limit_behavior = output_limit_behavior;
+    }
 
-        Ok(())
+    /// Makes `&` and `~` push -1 without touching the input once they have
+    /// read `max_reads` times in one run of the program, so a program looping
+    /// on an endless reader still ends. Unlimited by default.
+    pub fn set_input_limit(&mut self, max_reads: usize) {
+        self.input_limit = Some(max_reads);
     }
 
-    fn logical_not(&mut self) -> InterpreterResult<()> {
-        let a = self.stack.pop();
-        let n = if a == 0 { 1 } else { 0 };
-        self.stack.push(n);
+    /// Starts recording the loaded program along with the input it consumes
+    /// and the directions `?` takes, for [`Interpreter::replay`]. Recording
+    /// starts over whenever the program does, e.g. when a program is loaded,
+    /// so call this before running. Directions replayed after
+    /// [`Interpreter::step_back`] are not recorded again.
+    pub fn record(&mut self) {
+        let source = self
+            .original_program
+            .iter()
+            .map(|line| line.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        self.recording = Some(Recording {
+            source,
+            input: Vec::new(),
+            directions: Vec::new(),
+        });
+    }
 
-        Ok(())
+    /// Returns what has been recorded since [`Interpreter::record`].
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
     }
 
-    fn greater_than(&mut self) -> InterpreterResult<()> {
-        let (a, b) = self.stack.pop2();
-        let n = if b > a { 1 } else { 0 };
-        self.stack.push(n);
+    /// Makes `~` and `&` write the input they consume to the output.
+    pub fn set_echo_input(&mut self, echo_input: bool) {
+        self.echo_input = echo_input;
+    }
 
-        Ok(())
+    pub fn set_input_mode(&mut self, input_mode: InputMode) {
+        self.input_mode = input_mode;
     }
 
-    fn start_moving_right(&mut self) -> InterpreterResult<()> {
-        self.direction = Direction::Right;
+    pub fn set_string_mode(&mut self, string_mode: StringMode) {
+        self.string_mode = string_mode;
+    }
 
-        Ok(())
+    /// Makes [`Interpreter::load_program`] drop lines starting with the given
+    /// prefix before building the playfield. Comments are not part of the
+    /// grid, so they do not affect its dimensions or coordinates. An empty
+    /// prefix is treated like `None`, as every line would start with it.
+    pub fn set_comment_prefix(&mut self, prefix: Option<&str>) {
+        self.comment_prefix = prefix.filter(|prefix| !prefix.is_empty()).map(String::from);
     }
 
-    fn start_moving_left(&mut self) -> InterpreterResult<()> {
-        self.direction = Direction::Left;
+    /// Sets the value popped from an empty stack. Befunge-93 specifies 0, so
+    /// any other value makes the interpreter deviate from the spec.
+    pub fn set_stack_default(&mut self, default: isize) {
+        self.stack.default = default;
+    }
+
+    /// Writes the stack bottom to top, one integer per line.
+    pub fn save_stack(&self, w: &mut impl Write) -> io::Result<()> {
+        for n in self.stack.values() {
+            writeln!(w, "{}", n)?;
+        }
 
         Ok(())
     }
 
-    fn start_moving_up(&mut self) -> InterpreterResult<()> {
-        self.direction = Direction::Up;
+    /// Replaces the stack with integers read one per line, bottom to top, as
+    /// written by [`Interpreter::save_stack`]. Blank lines are skipped. Clears
+    /// the history of [`Interpreter::step_back`].
+    pub fn load_stack_from(&mut self, r: &mut impl BufRead) -> InterpreterResult<()> {
+        let mut stack = Vec::new();
+
+        for line in r.lines() {
+            let line = line?;
+            let line = line.trim();
+
+            if !line.is_empty() {
+                stack.push(line.parse()?);
+            }
+        }
+
+        self.stack.set_values(&stack);
+        self.history.deltas.clear();
+        self.history.replay.clear();
+        self.update_stack_watches();
 
         Ok(())
     }
 
-    fn start_moving_down(&mut self) -> InterpreterResult<()> {
-        self.direction = Direction::Down;
+    /// Makes instructions fail with `InterpreterError::StackOverflow` instead
+    /// of growing the stack beyond `max` values. The stack is unlimited by
+    /// default.
+    pub fn set_stack_limit(&mut self, max: usize) {
+        self.stack.limit = Some(max);
+    }
 
-        Ok(())
+    /// Sets a byte written after every integer output by `.`.
+    pub fn set_separator(&mut self, separator: Option<u8>) {
+        self.separator = separator;
     }
 
-    fn start_moving_randomly(&mut self) -> InterpreterResult<()> {
-        let direction = DIRECTIONS
-            .choose(&mut self.gen)
-            .expect("directions is not empty");
-        self.direction = *direction;
+    /// Makes `.` write a space after every integer like the reference
+    /// Befunge-93 interpreter, so programs written for it print as intended.
+    /// This changes the output of every `.` and is off by default. A separator
+    /// set with [`Interpreter::set_separator`] follows the space.
+    pub fn set_reference_compat(&mut self, reference_compat: bool) {
+        self.reference_compat = reference_compat;
+    }
 
-        Ok(())
+    pub fn set_oversize_behavior(&mut self, oversize_behavior: OversizeBehavior) {
+        self.oversize_behavior = oversize_behavior;
     }
 
-    fn horizontal_if(&mut self) -> InterpreterResult<()> {
-        let n = self.stack.pop();
-        self.direction = if n == 0 {
-            Direction::Right
-        } else {
-            Direction::Left
-        };
+    pub fn set_wrap_mode(&mut self, wrap_mode: WrapMode) {
+        self.wrap_mode = wrap_mode;
+    }
 
-        Ok(())
+    pub fn set_div_by_zero(&mut self, div_by_zero: DivByZero) {
+        self.div_by_zero = div_by_zero;
     }
 
-    fn vertical_if(&mut self) -> InterpreterResult<()> {
-        let n = self.stack.pop();
-        self.direction = if n == 0 {
-            Direction::Down
-        } else {
-            Direction::Up
-        };
+    /// Registers a callback invoked with the `(x, y)` coordinates before and
+    /// after the PC wraps around an edge of the playfield, and the direction
+    /// it was moving in.
+    pub fn set_wrap_hook(
+        &mut self,
+        hook: impl FnMut((usize, usize), (usize, usize), Direction) + 'static,
+    ) {
+        self.wrap_hook = Some(Hook(Box::new(hook)));
+    }
 
-        Ok(())
+    /// Makes [`Interpreter::run`] execute straight-line stretches of the
+    /// program from a cache of instruction handlers instead of decoding every
+    /// cell. Results are identical to the interpreted path. Has no effect
+    /// while history, coverage tracking, profiling or stack watches are
+    /// enabled, or while a step limit is set.
+    pub fn set_fast_path(&mut self, fast_path: bool) {
+        self.fast_path = fast_path;
+        self.invalidate_fast_runs();
     }
 
-    fn toggle_string_mode(&mut self) -> InterpreterResult<()> {
-        self.mode = if self.mode == Mode::Normal {
-            Mode::String
-        } else {
-            Mode::Normal
-        };
+    /// Enables recording which cells the PC has executed, see
+    /// [`Interpreter::visited_cells`].
+    pub fn set_track_coverage(&mut self, track_coverage: bool) {
+        self.track_coverage = track_coverage;
+    }
 
-        Ok(())
+    /// Returns `(x, y)` coordinates of every cell executed since the program
+    /// was loaded. Only populated while coverage tracking is enabled.
+    pub fn visited_cells(&self) -> &HashSet<(usize, usize)> {
+        &self.visited_cells
     }
 
-    fn duplicate_top_of_the_stack(&mut self) -> InterpreterResult<()> {
-        let n = self.stack.pop();
-        self.stack.push(n);
-        self.stack.push(n);
+    /// Returns the fraction of non-space cells that have been executed.
+    pub fn coverage_ratio(&self) -> f64 {
+        let mut total = 0;
+        let mut visited = 0;
 
-        Ok(())
+        for (i, line) in self.program.iter().enumerate() {
+            for (j, &c) in line.iter().enumerate() {
+                if c != ' ' {
+                    total += 1;
+
+                    if self.visited_cells.contains(&(j, i)) {
+                        visited += 1;
+                    }
+                }
+            }
+        }
+
+        if total == 0 {
+            return 0.0;
+        }
+
+        visited as f64 / total as f64
     }
 
-    fn swap_top_stack_values(&mut self) -> InterpreterResult<()> {
-        let (a, b) = self.stack.pop2();
-        self.stack.push(a);
-        self.stack.push(b);
+    /// Enables counting how many times each cell is executed, see
+    /// [`Interpreter::cell_hotspots`].
+    pub fn set_profile(&mut self, profile: bool) {
+        self.profile = profile;
+    }
 
-        Ok(())
+    /// Returns `(x, y)` coordinates of every cell executed since the program
+    /// was loaded with the number of executions, most executed first. Only
+    /// populated while profiling is enabled.
+    pub fn cell_hotspots(&self) -> Vec<((usize, usize), u64)> {
+        let mut hotspots: Vec<_> = self
+            .cell_counts
+            .iter()
+            .map(|(&cell, &count)| (cell, count))
+            .collect();
+        hotspots.sort_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then((a.1, a.0).cmp(&(b.1, b.0)))
+        });
+
+        hotspots
     }
 
-    fn pop_and_discard(&mut self) -> InterpreterResult<()> {
-        let _ = self.stack.pop();
+    /// Registers a callback invoked whenever an instruction pushes a value to
+    /// or pops one from the stack. Popping an empty stack fires no event.
+    pub fn set_stack_hook(&mut self, hook: impl FnMut(StackEvent) + 'static) {
+        self.stack.hook = Some(Hook(Box::new(hook)));
+    }
 
-        Ok(())
+    /// Registers a callback invoked whenever `&` or `~` consumes input, with
+    /// the bytes read and the value pushed. Input that ends in an error and
+    /// values replayed after [`Interpreter::step_back`] fire no event.
+    pub fn set_input_hook(&mut self, hook: impl FnMut(InputEvent) + 'static) {
+        self.input_hook = Some(Hook(Box::new(hook)));
     }
 
-    fn pop_and_output_int(&mut self) -> InterpreterResult<()> {
-        let n = self.stack.pop().to_string();
-        let x = n.as_bytes();
-        self.output.write_all(x)?;
+    /// Registers a callback invoked once, after the step that halts the
+    /// program.
+    pub fn set_halt_hook(&mut self, hook: impl FnOnce(&Self) + 'static) {
+        self.halt_hook = Some(Hook(Box::new(hook)));
+    }
 
-        Ok(())
+    /// Returns `(x, y)` coordinates of cells from the original source that
+    /// were overwritten with a different character by `p`.
+    pub fn self_modifications(&self) -> &[(usize, usize)] {
+        &self.self_modifications
     }
 
-    fn pop_and_output_char(&mut self) -> InterpreterResult<()> {
-        let c = self.pop_ascii()?;
-        let n = c as u8;
-        self.output.write_all(&[n])?;
+    /// Returns `(x, y, original, current)` for every cell that differs from
+    /// the grid as it was loaded. Cells added by [`Interpreter::resize_grid`]
+    /// were originally spaces.
+    pub fn grid_diff(&self) -> Vec<(usize, usize, char, char)> {
+        let mut diff = Vec::new();
 
-        Ok(())
+        for (i, line) in self.program.iter().enumerate() {
+            for (j, &c) in line.iter().enumerate() {
+                let original = self.original_cell((i, j));
+
+                if original != c {
+                    diff.push((j, i, original, c));
+                }
+            }
+        }
+
+        diff
     }
 
-    fn get_int_and_push(&mut self) -> InterpreterResult<()> {
-        let mut s = String::new();
-        self.input.read_line(&mut s)?;
-        let n: isize = s.trim().parse()?;
-        self.stack.push(n);
+    /// Returns the character at `(i, j)` when the grid was loaded.
+    fn original_cell(&self, (i, j): ProgramCounter) -> char {
+        self.original_program
+            .get(i)
+            .and_then(|line| line.get(j))
+            .copied()
+            .unwrap_or(' ')
+    }
 
-        Ok(())
+    /// Loads the program, padding every row with spaces to the longest line.
+    /// A single trailing newline does not start a new row, but blank lines
+    /// before it are kept, so `"@\n"` is one row tall and `"@\n\n"` is two.
+    pub fn load_program(&mut self, program: &str) -> InterpreterResult<()> {
+        let grid = program
+            .lines()
+            .filter(|line| match &self.comment_prefix {
+                Some(prefix) => !line.starts_with(prefix.as_str()),
+                None => true,
+            })
+            .map(|line| line.chars().collect())
+            .collect();
+
+        self.load_grid(grid)
     }
 
-    fn get_char_and_push(&mut self) -> InterpreterResult<()> {
-        let mut s: [u8; 1] = [0; 1];
-        self.input.read_exact(&mut s)?;
+    /// Loads a program that isn't UTF-8 encoded, treating every byte as the
+    /// Latin-1 character of the same value, so `g` on a byte 0xC0 pushes 192.
+    /// Lines are split like in [`Interpreter::load_program`].
+    pub fn load_program_bytes(&mut self, bytes: &[u8]) -> InterpreterResult<()> {
+        let program: String = bytes.iter().map(|&b| char::from(b)).collect();
+
+        self.load_program(&program)
+    }
+
+    /// Loads an already split playfield, padding every row with spaces to the
+    /// longest one. Fails with `InterpreterError::InvalidCoordinates`, keeping
+    /// the previous program, if the start set by [`Interpreter::set_start`]
+    /// lies outside of it.
+    pub fn load_grid(&mut self, mut grid: Vec<Vec<char>>) -> InterpreterResult<()> {
+        let longest_line_len = grid.iter().map(|line| line.len()).max().unwrap_or(0);
+        let rows_len = grid.len();
+
+        if longest_line_len > MAX_WIDTH || rows_len > MAX_HEIGHT {
+            match self.oversize_behavior {
+                OversizeBehavior::Error => {
+                    return Err(InterpreterError::ProgramTooLarge {
+                        width: longest_line_len,
+                        height: rows_len,
+                    })
+                }
+                OversizeBehavior::Truncate => {
+                    grid.truncate(MAX_HEIGHT);
+                    for line in grid.iter_mut() {
+                        line.truncate(MAX_WIDTH);
+                    }
+
+                    return self.load_grid(grid);
+                }
+                OversizeBehavior::Allow => (),
+            }
+        }
+
+        if longest_line_len > 0 && rows_len > 0 {
+            let ((y, x), _) = self.start;
+            Self::check_start(x, y, longest_line_len, rows_len)?;
+        }
 
-        let n = s[0] as isize;
-        self.stack.push(n);
+        self.code_cells.clear();
+        self.self_modifications.clear();
+        self.visited_cells.clear();
+        self.cell_counts.clear();
+        self.invalidate_fast_runs();
+
+        for (i, line) in grid.iter_mut().enumerate() {
+            for (j, &c) in line.iter().enumerate() {
+                if c != ' ' {
+                    self.code_cells.insert((j, i));
+                }
+            }
+
+            line.resize(longest_line_len, ' ');
+        }
+
+        self.original_program = grid.clone();
+        self.program = grid;
+        self.width = longest_line_len;
+        self.height = rows_len;
+        self.restart();
+
+        debug_assert!(self.validate_grid());
 
         Ok(())
     }
 
-    fn bridge(&mut self) -> InterpreterResult<()> {
-        self.move_pc();
+    /// Puts the interpreter back into its initial state for the current grid.
+    fn restart(&mut self) {
+        self.stack.set_values(&self.initial_stack);
+        self.stack.journal = None;
+        (self.pc, self.direction) = self.start;
+        self.mode = Mode::Normal;
+        self.enabled = true;
+        self.random_index = 0;
+        self.output_written = 0;
+        self.input_reads = 0;
+        if self.recording.is_some() {
+            self.record();
+        }
+        self.history.deltas.clear();
+        self.history.replay.clear();
+        self.update_stack_watches();
+    }
 
-        Ok(())
+    /// Undoes every `p`, puts the interpreter back into its initial state and
+    /// replaces the random number generator, e.g. with a freshly seeded
+    /// `StdRng`, so a program using `?` can be run repeatedly with the same
+    /// results. Coverage and profiling data are cleared as well, but input
+    /// and output are left as they are.
+    pub fn reset_with_rng(&mut self, gen: G) {
+        self.restore_grid();
+        self.visited_cells.clear();
+        self.cell_counts.clear();
+        self.restart();
+        self.gen = gen;
     }
 
-    fn put(&mut self) -> InterpreterResult<()> {
-        let y = self.stack.pop();
-        let x = self.stack.pop();
+    /// Undoes every `p` by copying the loaded program back into the grid.
+    fn restore_grid(&mut self) {
+        for (i, line) in self.program.iter_mut().enumerate() {
+            for (j, c) in line.iter_mut().enumerate() {
+                *c = self
+                    .original_program
+                    .get(i)
+                    .and_then(|line| line.get(j))
+                    .copied()
+                    .unwrap_or(' ');
+            }
+        }
+
+        self.self_modifications.clear();
+        self.invalidate_fast_runs();
+    }
+
+    /// Pads the playfield with spaces to at least `width` columns and
+    /// `height` rows, e.g. to give programs that `p` outside of their source
+    /// text the full 80x25 field. The grid never shrinks.
+    pub fn resize_grid(&mut self, width: usize, height: usize) {
+        let width = width.max(self.width);
+        let height = height.max(self.height);
 
-        if y < 0 || x < 0 {
-            return Err(InterpreterError::InvalidCoordinates { x, y });
+        self.program.resize(height, Vec::new());
+        for line in self.program.iter_mut() {
+            line.resize(width, ' ');
         }
 
-        let y_ = y as usize;
-        let x_ = x as usize;
-        let v = self.pop_ascii()?;
+        self.width = width;
+        self.height = height;
+        self.invalidate_fast_runs();
 
-        let c = self
-            .program
-            .get_mut(y_)
-            .ok_or(InterpreterError::InvalidCoordinates { x, y })?
-            .get_mut(x_)
-            .ok_or(InterpreterError::InvalidCoordinates { x, y })?;
-        *c = v;
+        debug_assert!(self.validate_grid());
+    }
 
-        Ok(())
+    /// Counts the characters in the grid without running it. Characters are
+    /// counted wherever they are, including inside strings, so the flags are
+    /// conservative: a program may never execute an instruction it contains.
+    pub fn analyze(&self) -> ProgramStats {
+        let mut instructions = BTreeMap::new();
+
+        for &c in self.program.iter().flatten() {
+            if c != ' ' {
+                *instructions.entry(c).or_insert(0) += 1;
+            }
+        }
+
+        let contains = |c: &[char]| c.iter().any(|c| instructions.contains_key(c));
+        let self_modifying = contains(&['p', 'g']);
+        let reads_input = contains(&['&', '~']);
+        let halts = contains(&['@']);
+
+        ProgramStats {
+            instructions,
+            self_modifying,
+            reads_input,
+            halts,
+        }
     }
 
-    fn get(&mut self) -> InterpreterResult<()> {
-        let y = self.stack.pop();
-        let x = self.stack.pop();
-        let y_ = y as usize;
-        let x_ = x as usize;
+    /// Returns the `(x, y)` coordinates of every `@` in the grid, row by row.
+    /// Like [`Interpreter::analyze`] this does not tell code from string
+    /// literals. A program without any may still halt, as `p` can write an
+    /// `@` and `WrapMode::Halt`, the output limit or a broken pipe stop it
+    /// too, while one with some may run forever.
+    pub fn halt_cells(&self) -> Vec<(usize, usize)> {
+        let mut cells = Vec::new();
+
+        for (i, line) in self.program.iter().enumerate() {
+            for (j, &c) in line.iter().enumerate() {
+                if c == '@' {
+                    cells.push((j, i));
+                }
+            }
+        }
+
+        cells
+    }
+
+    /// Looks for an `@` outside of string literals, reading every row left to
+    /// right and toggling at each `"`. `TerminationHint::NoHalt` means the
+    /// program most likely loops forever; `TerminationHint::HasHalt` does not
+    /// mean the `@` is ever reached.
+    pub fn static_termination_check(&self) -> TerminationHint {
+        for line in self.program.iter() {
+            let mut in_string = false;
+
+            for &c in line.iter() {
+                match c {
+                    '"' => in_string = !in_string,
+                    '@' if !in_string => return TerminationHint::HasHalt,
+                    _ => (),
+                }
+            }
+        }
+
+        TerminationHint::NoHalt
+    }
+
+    /// Returns `(x, y, c)` for every cell holding a character outside of the
+    /// printable ASCII range.
+    pub fn validate_program(&self) -> Result<(), Vec<(usize, usize, char)>> {
+        let mut invalid = Vec::new();
+
+        for (i, line) in self.program.iter().enumerate() {
+            for (j, &c) in line.iter().enumerate() {
+                if !(' '..='~').contains(&c) {
+                    invalid.push((j, i, c));
+                }
+            }
+        }
+
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid)
+        }
+    }
+
+    /// Checks that the playfield is rectangular, i.e. it has `height` rows and
+    /// every row has exactly `width` cells.
+    pub fn validate_grid(&self) -> bool {
+        self.program.len() == self.height && self.program.iter().all(|row| row.len() == self.width)
+    }
+
+    /// Draws the playfield row by row with the cell under the PC wrapped in
+    /// brackets.
+    pub fn render(&self) -> String {
+        let mut rows = Vec::with_capacity(self.height);
+
+        for (i, line) in self.program.iter().enumerate() {
+            let mut row = String::with_capacity(self.width + 2);
+
+            for (j, &c) in line.iter().enumerate() {
+                if (i, j) == self.pc {
+                    row.push('[');
+                    row.push(c);
+                    row.push(']');
+                } else {
+                    row.push(c);
+                }
+            }
+
+            rows.push(row);
+        }
+
+        rows.join("\n")
+    }
+
+    /// Runs the program until it halts. Fails with
+    /// `InterpreterError::ProgramNotLoaded` if the loaded playfield is empty.
+    pub fn run(&mut self) -> InterpreterResult<()> {
+        if !self.is_loaded() {
+            return Err(InterpreterError::ProgramNotLoaded);
+        }
+
+        let stopwatch = self.collect_stats.then(Stopwatch::start);
+        let mut steps = 0;
+
+        let result = loop {
+            if !self.enabled {
+                break Ok(());
+            }
+
+            if self.step_limit.is_some_and(|limit| steps >= limit) {
+                break Ok(());
+            }
+
+            if self.can_use_fast_path() {
+                match self.execute_fast_run(&mut steps) {
+                    Ok(true) => continue,
+                    Ok(false) => (),
+                    Err(err) => break Err(err),
+                }
+            }
+
+            if let Err(err) = self.step() {
+                break Err(err);
+            }
+            steps += 1;
+        };
+
+        if let Some(stopwatch) = stopwatch {
+            let elapsed = stopwatch.elapsed();
+            self.last_run_stats = Some(RunStats { steps, elapsed });
+        }
+
+        self.last_halt_reason = Some(match result {
+            Err(_) => HaltReason::Errored,
+            Ok(()) if self.enabled => HaltReason::StepLimit,
+            Ok(()) => HaltReason::Halted,
+        });
 
-        let c = *self
-            .program
-            .get(y_)
-            .ok_or(InterpreterError::InvalidCoordinates { x, y })?
-            .get(x_)
-            .ok_or(InterpreterError::InvalidCoordinates { x, y })?;
+        result
+    }
 
-        self.stack.push(c as isize);
+    /// Executes a single instruction. Does nothing once the program has
+    /// halted and fails with `InterpreterError::ProgramNotLoaded` if the
+    /// loaded playfield is empty.
+    pub fn step(&mut self) -> InterpreterResult<()> {
+        self.step_detailed()?;
 
         Ok(())
     }
-}
 
-impl Default for Interpreter<StdinLock<'static>, Stdout, ThreadRng> {
-    fn default() -> Self {
-        Self::new(io::stdin().lock(), io::stdout(), rand::thread_rng())
+    /// Executes up to `n` instructions, stopping early once the program halts,
+    /// and calls `cb` after each of them, e.g. to redraw the grid. Returns
+    /// whether the program is still running.
+    pub fn run_steps_with_callback(
+        &mut self,
+        n: usize,
+        mut cb: impl FnMut(&Self),
+    ) -> InterpreterResult<StepOutcome> {
+        for _ in 0..n {
+            if !self.enabled {
+                break;
+            }
+
+            self.step()?;
+            cb(self);
+        }
+
+        Ok(if self.enabled {
+            StepOutcome::Running
+        } else {
+            StepOutcome::Halted
+        })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use io::Cursor;
-    use rand::{rngs::StdRng, SeedableRng};
+    /// Executes at most one instruction and reports whether the program is
+    /// still running, so a scheduler can drive many interpreters in turn.
+    /// Nothing loops internally: a string is pushed one character per call
+    /// and `#` moves the PC two cells but counts as a single call. Blocking
+    /// on input is up to the reader.
+    pub fn step_yielding(&mut self) -> InterpreterResult<StepOutcome> {
+        Ok(self.step_detailed()?.outcome)
+    }
 
-    use super::*;
+    /// Same as [`Interpreter::step`], but also reports which instruction was
+    /// executed and where the PC was before and after it.
+    pub fn step_detailed(&mut self) -> InterpreterResult<StepInfo> {
+        if !self.is_loaded() {
+            return Err(InterpreterError::ProgramNotLoaded);
+        }
 
-    fn build_interpreter() -> Interpreter<Cursor<Vec<u8>>, Cursor<Vec<u8>>, StdRng> {
-        let input = Cursor::new(Vec::new());
-        let output = Cursor::new(Vec::new());
-        let gen = StdRng::seed_from_u64(123);
+        let instruction = self.get_instruction();
+        let (i, j) = self.pc;
+
+        if self.enabled {
+            if self.track_coverage {
+                self.visited_cells.insert((j, i));
+            }
+
+            if self.profile {
+                *self.cell_counts.entry((j, i)).or_insert(0) += 1;
+            }
+
+            if self.history.limit == 0 {
+                self.execute()?;
+            } else {
+                self.execute_recorded()?;
+            }
+
+            self.update_stack_watches();
+
+            if !self.enabled {
+                self.call_halt_hook();
+            }
+        }
+
+        let pc_before = (j, i);
+        let pc_after = (self.pc.1, self.pc.0);
+        let outcome = if self.enabled {
+            StepOutcome::Running
+        } else {
+            StepOutcome::Halted
+        };
+
+        Ok(StepInfo {
+            instruction,
+            pc_before,
+            pc_after,
+            outcome,
+        })
+    }
+
+    fn stack_value_at_depth(&self, depth: usize) -> Option<isize> {
+        let stack = self.stack.values();
+        let index = stack.len().checked_sub(depth + 1)?;
+
+        Some(stack[index])
+    }
+
+    fn update_stack_watches(&mut self) {
+        let stack = self.stack.values();
+
+        for watch in self.stack_watches.iter_mut() {
+            let value = stack
+                .len()
+                .checked_sub(watch.depth + 1)
+                .map(|index| stack[index]);
+            watch.changed = value != watch.value;
+            watch.value = value;
+        }
+    }
+
+    fn call_halt_hook(&mut self) {
+        if let Some(Hook(hook)) = self.halt_hook.take() {
+            hook(self);
+        }
+    }
+
+    fn can_use_fast_path(&self) -> bool {
+        self.fast_path
+            && self.mode == Mode::Normal
+            && self.history.limit == 0
+            && self.history.replay.is_empty()
+            && !self.track_coverage
+            && !self.profile
+            && self.stack_watches.is_empty()
+            && self.step_limit.is_none()
+    }
+
+    /// Executes the cached straight-line run starting at the PC, building it
+    /// first if needed. Returns `false` if the current instruction can't
+    /// start a run and has to be stepped normally.
+    fn execute_fast_run(&mut self, steps: &mut u64) -> InterpreterResult<bool> {
+        let key = (self.pc, self.direction);
+        let handlers = match self.fast_runs.get(&key) {
+            Some(handlers) => handlers.clone(),
+            None => {
+                let handlers = self.build_fast_run();
+                self.fast_runs.insert(key, handlers.clone());
+                handlers
+            }
+        };
+
+        if handlers.is_empty() {
+            return Ok(false);
+        }
+
+        for handler in handlers.iter() {
+            self.history.choice = None;
+            handler(self)?;
+            self.move_pc();
+            *steps += 1;
+
+            if !self.enabled {
+                break;
+            }
+        }
+
+        if !self.enabled {
+            self.call_halt_hook();
+        }
+
+        Ok(true)
+    }
+
+    /// Follows the current direction from the PC while the instructions
+    /// neither change the control flow nor write to the grid. A run stops at
+    /// the edge, so wrapping is always handled by the last instruction.
+    fn build_fast_run(&mut self) -> Rc<[Handler<R, W, G>]> {
+        let mut handlers = Vec::new();
+        let (mut i, mut j) = self.pc;
+
+        while let Some(handler) = Self::fast_handler(self.program[i][j]) {
+            handlers.push(handler);
+            self.fast_cells.insert((j, i));
+
+            (i, j) = match self.direction {
+                Direction::Left if j > 0 => (i, j - 1),
+                Direction::Right if j + 1 < self.width => (i, j + 1),
+                Direction::Up if i > 0 => (i - 1, j),
+                Direction::Down if i + 1 < self.height => (i + 1, j),
+                _ => break,
+            };
+        }
+
+        handlers.into()
+    }
+
+    fn fast_handler(instruction: char) -> Option<Handler<R, W, G>> {
+        let handler: Handler<R, W, G> = match instruction {
+            '+' => Self::add,
+            '-' => Self::subtract,
+            '*' => Self::multiply,
+            '/' => Self::divide,
+            '%' => Self::remainder,
+            '!' => Self::logical_not,
+            '`' => Self::greater_than,
+            ':' => Self::duplicate_top_of_the_stack,
+            '\\' => Self::swap_top_stack_values,
+            '$' => Self::pop_and_discard,
+            '.' => Self::pop_and_output_int,
+            ',' => Self::pop_and_output_char,
+            'g' => Self::get,
+            '&' => Self::get_int_and_push,
+            '~' => Self::get_char_and_push,
+            ' ' => |_| Ok(()),
+            '0'..='9' => Self::push_digit_to_stack,
+            _ => return None,
+        };
+
+        Some(handler)
+    }
+
+    fn invalidate_fast_runs(&mut self) {
+        self.fast_runs.clear();
+        self.fast_cells.clear();
+    }
+
+    fn execute_recorded(&mut self) -> InterpreterResult<()> {
+        let pc = self.pc;
+        let direction = self.direction;
+        let mode = self.mode;
+        let enabled = self.enabled;
+        self.history.cell = None;
+        self.stack.begin_journal();
+
+        let result = self.execute();
+
+        let stack = self.stack.end_journal().expect("journal was started");
+        let delta = Delta {
+            pc,
+            direction,
+            mode,
+            enabled,
+            stack,
+            cell: self.history.cell.take(),
+            choice: self.history.choice.take(),
+        };
+
+        if self.history.deltas.len() == self.history.limit {
+            self.history.deltas.pop_front();
+        }
+        self.history.deltas.push_back(delta);
+
+        result
+    }
+
+    /// Reverts the most recently recorded step. Input and `?` choices of the
+    /// reverted step are replayed when stepping forward again.
+    pub fn step_back(&mut self) -> InterpreterResult<()> {
+        let delta = match self.history.deltas.pop_back() {
+            Some(delta) => delta,
+            None => return Ok(()),
+        };
+
+        if let Some(((i, j), c)) = delta.cell {
+            self.program[i][j] = c;
+            if self.original_cell((i, j)) == c {
+                self.self_modifications.retain(|&cell| cell != (j, i));
+            }
+            self.invalidate_fast_runs();
+        }
+
+        self.stack.revert(delta.stack);
+        self.pc = delta.pc;
+        self.direction = delta.direction;
+        self.mode = delta.mode;
+        self.enabled = delta.enabled;
+        self.history.replay.push(delta.choice);
+        self.update_stack_watches();
+
+        Ok(())
+    }
+
+    fn execute(&mut self) -> InterpreterResult<()> {
+        self.history.choice = self.history.replay.pop().flatten();
+        let instruction = self.get_instruction();
+
+        if self.mode == Mode::String {
+            if instruction == '"' {
+                self.toggle_string_mode()?;
+            } else {
+                self.push_string_char(instruction)?;
+            }
+        } else {
+            match instruction {
+                '+' => self.add()?,
+                '-' => self.subtract()?,
+                '*' => self.multiply()?,
+                '/' => self.divide()?,
+                '%' => self.remainder()?,
+                '!' => self.logical_not()?,
+                '`' => self.greater_than()?,
+                '>' => self.start_moving_right()?,
+                '<' => self.start_moving_left()?,
+                '^' => self.start_moving_up()?,
+                'v' => self.start_moving_down()?,
+                '?' => self.start_moving_randomly()?,
+                '_' => self.horizontal_if()?,
+                '|' => self.vertical_if()?,
+                '"' => self.toggle_string_mode()?,
+                ':' => self.duplicate_top_of_the_stack()?,
+                '\\' => self.swap_top_stack_values()?,
+                '$' => self.pop_and_discard()?,
+                '.' => self.pop_and_output_int()?,
+                ',' => self.pop_and_output_char()?,
+                '#' => self.bridge()?,
+                'p' => self.put()?,
+                'g' => self.get()?,
+                '&' => self.get_int_and_push()?,
+                '~' => self.get_char_and_push()?,
+                ' ' => (),
+                '@' => self.enabled = false,
+                _ if instruction.is_ascii_digit() => self.push_digit_to_stack()?,
+                i => return Err(InterpreterError::UnknownInstruction(i)),
+            };
+        }
+
+        self.move_pc();
+
+        Ok(())
+    }
+
+    fn push_string_char(&mut self, c: char) -> InterpreterResult<()> {
+        let n = match self.string_mode {
+            StringMode::Byte => c as u8 as isize,
+            StringMode::Unicode => c as isize,
+            StringMode::Ascii if c.is_ascii() => c as isize,
+            StringMode::Ascii => {
+                let (i, j) = self.pc;
+                return Err(InterpreterError::NonAsciiInString {
+                    x: j,
+                    y: i,
+                    character: c,
+                });
+            }
+        };
+
+        self.stack.push(n)
+    }
+
+    fn is_loaded(&self) -> bool {
+        self.width > 0 && self.height > 0
+    }
+
+    fn pop_ascii(&mut self) -> InterpreterResult<char> {
+        let v__ = self.stack.pop();
+        let v_: u8 = v__
+            .try_into()
+            .or(Err(InterpreterError::InvalidAscii(v__)))?;
+        let v = v_ as char;
+
+        Ok(v)
+    }
+
+    fn get_instruction(&self) -> char {
+        let (i, j) = self.pc;
+        self.program[i][j]
+    }
+
+    /// Moves the PC one cell in the current direction. The playfield is a
+    /// torus: leaving through an edge re-enters on the opposite edge of the
+    /// same row or column, so on a grid one cell wide (or tall) the PC stays
+    /// in place along that axis. Under `WrapMode::Halt` the PC stays on the
+    /// edge and the program halts instead.
+    fn move_pc(&mut self) {
+        let (i, j) = self.pc;
+
+        let (next, wrapped) = match self.direction {
+            Direction::Left if j == 0 => ((i, self.width - 1), true),
+            Direction::Left => ((i, j - 1), false),
+            Direction::Right if j + 1 == self.width => ((i, 0), true),
+            Direction::Right => ((i, j + 1), false),
+            Direction::Up if i == 0 => ((self.height - 1, j), true),
+            Direction::Up => ((i - 1, j), false),
+            Direction::Down if i + 1 == self.height => ((0, j), true),
+            Direction::Down => ((i + 1, j), false),
+        };
+
+        if wrapped && self.wrap_mode == WrapMode::Halt {
+            self.enabled = false;
+            return;
+        }
+        self.pc = next;
+
+        if wrapped {
+            if let Some(Hook(hook)) = &mut self.wrap_hook {
+                hook((j, i), (next.1, next.0), self.direction);
+            }
+        }
+    }
+
+    fn push_digit_to_stack(&mut self) -> InterpreterResult<()> {
+        let instruction = self.get_instruction();
+        let n = char::to_digit(instruction, 10).expect("is digit") as isize;
+        self.stack.push(n)?;
+
+        Ok(())
+    }
+
+    fn add(&mut self) -> InterpreterResult<()> {
+        let (a, b) = self.stack.pop2();
+        self.stack.push(a + b)?;
+
+        Ok(())
+    }
+
+    fn subtract(&mut self) -> InterpreterResult<()> {
+        let (a, b) = self.stack.pop2();
+        self.stack.push(b - a)?;
+
+        Ok(())
+    }
+
+    fn multiply(&mut self) -> InterpreterResult<()> {
+        let (a, b) = self.stack.pop2();
+        self.stack.push(a * b)?;
+
+        Ok(())
+    }
+
+    fn divide(&mut self) -> InterpreterResult<()> {
+        let (a, b) = self.stack.pop2();
+        let n = if a == 0 {
+            self.divided_by_zero(b)?
+        } else {
+            b / a
+        };
+        self.stack.push(n)?;
+
+        Ok(())
+    }
+
+    fn remainder(&mut self) -> InterpreterResult<()> {
+        let (a, b) = self.stack.pop2();
+        let n = if a == 0 {
+            self.divided_by_zero(b)?
+        } else {
+            b % a
+        };
+        self.stack.push(n)?;
+
+        Ok(())
+    }
+
+    fn divided_by_zero(&self, x: isize) -> InterpreterResult<isize> {
+        match self.div_by_zero {
+            DivByZero::Zero => Ok(0),
+            DivByZero::Error => Err(InterpreterError::DivisionByZero { x, y: 0 }),
+        }
+    }
+
+    fn logical_not(&mut self) -> InterpreterResult<()> {
+        let a = self.stack.pop();
+        let n = if a == 0 { 1 } else { 0 };
+        self.stack.push(n)?;
+
+        Ok(())
+    }
+
+    fn greater_than(&mut self) -> InterpreterResult<()> {
+        let (a, b) = self.stack.pop2();
+        let n = if b > a { 1 } else { 0 };
+        self.stack.push(n)?;
+
+        Ok(())
+    }
+
+    fn start_moving_right(&mut self) -> InterpreterResult<()> {
+        self.direction = Direction::Right;
+
+        Ok(())
+    }
+
+    fn start_moving_left(&mut self) -> InterpreterResult<()> {
+        self.direction = Direction::Left;
+
+        Ok(())
+    }
+
+    fn start_moving_up(&mut self) -> InterpreterResult<()> {
+        self.direction = Direction::Up;
+
+        Ok(())
+    }
+
+    fn start_moving_down(&mut self) -> InterpreterResult<()> {
+        self.direction = Direction::Down;
+
+        Ok(())
+    }
+
+    fn start_moving_randomly(&mut self) -> InterpreterResult<()> {
+        let direction = match self.history.choice {
+            Some(Choice::Direction(direction)) => direction,
+            _ => {
+                let direction = match self.scripted_directions.pop_front() {
+                    Some(direction) => direction,
+                    None if self.random_mode == RandomMode::RoundRobin => {
+                        let direction = DIRECTIONS[self.random_index];
+                        self.random_index = (self.random_index + 1) % DIRECTIONS.len();
+                        direction
+                    }
+                    None => *DIRECTIONS
+                        .choose(&mut self.gen)
+                        .expect("directions is not empty"),
+                };
+                if let Some(recording) = &mut self.recording {
+                    recording.directions.push(direction);
+                }
+                direction
+            }
+        };
+        self.history.choice = Some(Choice::Direction(direction));
+        self.direction = direction;
+
+        Ok(())
+    }
+
+    fn horizontal_if(&mut self) -> InterpreterResult<()> {
+        let n = self.stack.pop();
+        self.direction = if n == 0 {
+            Direction::Right
+        } else {
+            Direction::Left
+        };
+
+        Ok(())
+    }
+
+    fn vertical_if(&mut self) -> InterpreterResult<()> {
+        let n = self.stack.pop();
+        self.direction = if n == 0 {
+            Direction::Down
+        } else {
+            Direction::Up
+        };
+
+        Ok(())
+    }
+
+    fn toggle_string_mode(&mut self) -> InterpreterResult<()> {
+        self.mode = if self.mode == Mode::Normal {
+            Mode::String
+        } else {
+            Mode::Normal
+        };
+
+        Ok(())
+    }
+
+    fn duplicate_top_of_the_stack(&mut self) -> InterpreterResult<()> {
+        self.stack.dup_top()?;
+
+        Ok(())
+    }
+
+    fn swap_top_stack_values(&mut self) -> InterpreterResult<()> {
+        self.stack.swap_top()?;
+
+        Ok(())
+    }
+
+    fn pop_and_discard(&mut self) -> InterpreterResult<()> {
+        let _ = self.stack.pop();
+
+        Ok(())
+    }
+
+    fn pop_and_output_int(&mut self) -> InterpreterResult<()> {
+        let mut x = self.stack.pop().to_string().into_bytes();
+
+        if self.reference_compat {
+            x.push(b' ');
+        }
+        if let Some(separator) = self.separator {
+            x.push(separator);
+        }
+
+        self.write_output(&x)
+    }
+
+    fn pop_and_output_char(&mut self) -> InterpreterResult<()> {
+        let c = self.pop_ascii()?;
+        let n = c as u8;
+
+        self.write_output(&[n])
+    }
+
+    fn write_output(&mut self, bytes: &[u8]) -> InterpreterResult<()> {
+        if let Some(limit) = self.output_limit {
+            if self.output_written + bytes.len() > limit {
+                return match self.output_limit_behavior {
+                    OutputLimitBehavior::Error => {
+                        Err(InterpreterError::OutputLimitExceeded { limit })
+                    }
+                    OutputLimitBehavior::Halt => {
+                        self.enabled = false;
+                        Ok(())
+                    }
+                };
+            }
+        }
+
+        match self.output.write_all(bytes) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe && self.halt_on_broken_pipe => {
+                self.enabled = false;
+                Ok(())
+            }
+            result => {
+                result?;
+                self.output_written += bytes.len();
+                Ok(())
+            }
+        }
+    }
+
+    fn get_int_and_push(&mut self) -> InterpreterResult<()> {
+        let n = match self.history.choice {
+            Some(Choice::Input(n)) => n,
+            _ if self.input_exhausted() => -1,
+            _ => {
+                let mut consumed = Vec::new();
+                let n = self.read_int(&mut consumed)?;
+                self.input_reads += 1;
+                self.emit_input(consumed, n);
+                n
+            }
+        };
+        self.history.choice = Some(Choice::Input(n));
+        self.stack.push(n)?;
+
+        Ok(())
+    }
+
+    /// Reads an integer, skipping leading whitespace and stopping at the
+    /// first character that can't be part of it. A line break (`\n`, `\r\n`
+    /// or `\r`) right after the number is consumed too, anything else stays
+    /// in the input for the next read.
+    fn read_int(&mut self, consumed: &mut Vec<u8>) -> InterpreterResult<isize> {
+        while self.consume_input_if(consumed, |b| b.is_ascii_whitespace())? {}
+        let start = consumed.len();
+        self.consume_input_if(consumed, |b| b == b'-' || b == b'+')?;
+        while self.consume_input_if(consumed, |b| b.is_ascii_digit())? {}
+
+        let result = String::from_utf8_lossy(&consumed[start..]).parse();
+
+        if result.is_ok()
+            && !self.consume_input_if(consumed, |b| b == b'\n')?
+            && self.consume_input_if(consumed, |b| b == b'\r')?
+        {
+            self.consume_input_if(consumed, |b| b == b'\n')?;
+        }
+
+        if self.echo_input {
+            self.write_output(consumed)?;
+        }
+
+        Ok(result?)
+    }
+
+    /// Consumes the next input byte if it matches `pred`.
+    fn consume_input_if(
+        &mut self,
+        consumed: &mut Vec<u8>,
+        pred: impl Fn(u8) -> bool,
+    ) -> io::Result<bool> {
+        match self.input.fill_buf()?.first() {
+            Some(&b) if pred(b) => {
+                self.input.consume(1);
+                consumed.push(b);
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn get_char_and_push(&mut self) -> InterpreterResult<()> {
+        let n = match self.history.choice {
+            Some(Choice::Input(n)) => n,
+            _ if self.input_exhausted() => -1,
+            _ => {
+                let mut consumed = Vec::new();
+                let n = match self.input_mode {
+                    InputMode::Bytes => {
+                        let mut s: [u8; 1] = [0; 1];
+                        self.input.read_exact(&mut s)?;
+                        consumed.push(s[0]);
+
+                        if self.echo_input {
+                            self.write_output(&s)?;
+                        }
+
+                        s[0] as isize
+                    }
+                    InputMode::Utf8 => self.read_utf8_char(&mut consumed)?,
+                };
+                self.input_reads += 1;
+                self.emit_input(consumed, n);
+                n
+            }
+        };
+        self.history.choice = Some(Choice::Input(n));
+        self.stack.push(n)?;
+
+        Ok(())
+    }
+
+    /// Reads one UTF-8 encoded character and returns its code point. A
+    /// sequence cut short by the end of input gives -1 and an invalid one
+    /// gives U+FFFD.
+    fn read_utf8_char(&mut self, consumed: &mut Vec<u8>) -> InterpreterResult<isize> {
+        let mut s: [u8; 4] = [0; 4];
+        self.input.read_exact(&mut s[..1])?;
+
+        let len = match s[0] {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+
+        let mut read = 1;
+        while read < len {
+            match self.input.read(&mut s[read..len]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        consumed.extend_from_slice(&s[..read]);
+
+        if self.echo_input {
+            self.write_output(&s[..read])?;
+        }
+
+        if read < len {
+            return Ok(-1);
+        }
+
+        let c = std::str::from_utf8(&s[..len])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+
+        Ok(c as isize)
+    }
+
+    fn input_exhausted(&self) -> bool {
+        self.input_limit
+            .is_some_and(|limit| self.input_reads >= limit)
+    }
+
+    fn emit_input(&mut self, bytes: Vec<u8>, value: isize) {
+        if let Some(recording) = &mut self.recording {
+            recording.input.extend_from_slice(&bytes);
+        }
+
+        if let Some(Hook(hook)) = &mut self.input_hook {
+            let (i, j) = self.pc;
+            hook(InputEvent {
+                pc: (j, i),
+                bytes,
+                value,
+            });
+        }
+    }
+
+    /// Skips the next cell by moving the PC once here and once more at the end
+    /// of `step`. Both moves wrap, so a `#` on an edge skips the first cell of
+    /// the opposite edge, and on a grid two cells wide the PC lands back on
+    /// the `#` itself. Under `WrapMode::Halt` whichever move would leave the
+    /// grid halts and keeps the PC in place, so the other one halts again on
+    /// the same cell without calling any hook a second time.
+    fn bridge(&mut self) -> InterpreterResult<()> {
+        self.move_pc();
+
+        Ok(())
+    }
+
+    fn put(&mut self) -> InterpreterResult<()> {
+        let y = self.stack.pop();
+        let x = self.stack.pop();
+        let (x_, y_) = self.to_cell(x, y)?;
+        let v = self.pop_ascii()?;
+
+        let c = &mut self.program[y_][x_];
+
+        if self.history.limit > 0 {
+            self.history.cell = Some(((y_, x_), *c));
+        }
+
+        if *c != v
+            && self.code_cells.contains(&(x_, y_))
+            && !self.self_modifications.contains(&(x_, y_))
+        {
+            self.self_modifications.push((x_, y_));
+        }
+        let changed = *c != v;
+        *c = v;
+
+        if changed && self.fast_cells.contains(&(x_, y_)) {
+            self.invalidate_fast_runs();
+        }
+
+        Ok(())
+    }
+
+    fn get(&mut self) -> InterpreterResult<()> {
+        let y = self.stack.pop();
+        let x = self.stack.pop();
+        let (x_, y_) = self.to_cell(x, y)?;
+
+        let c = self.program[y_][x_];
+
+        self.stack.push(c as isize)?;
+
+        Ok(())
+    }
+
+    /// Checks `(x, y)` against the playfield dimensions, so every cell
+    /// accepted here can be indexed directly and a grid with no rows or
+    /// columns rejects them all.
+    fn to_cell(&self, x: isize, y: isize) -> InterpreterResult<(usize, usize)> {
+        match (usize::try_from(x), usize::try_from(y)) {
+            (Ok(x_), Ok(y_)) if x_ < self.width && y_ < self.height => Ok((x_, y_)),
+            _ => Err(InterpreterError::InvalidCoordinates { x, y }),
+        }
+    }
+}
+
+impl<G: Rng> Interpreter<Cursor<Vec<u8>>, Cursor<Vec<u8>>, G> {
+    /// Runs the loaded program from the start, with every `p` of earlier runs
+    /// undone, against `input` and returns what it wrote. The interpreter's
+    /// own input and output are left as they were.
+    pub fn run_with_input(&mut self, input: &str) -> InterpreterResult<Vec<u8>> {
+        self.run_with_input_bytes(input.as_bytes().to_vec())
+    }
+
+    /// Runs a recorded program with this interpreter's settings until it
+    /// halts, feeding it the recorded input and making `?` take the recorded
+    /// directions, and returns its output. Once the directions run out `?`
+    /// draws from the interpreter's generator again. The recorded program
+    /// replaces the loaded one, while the interpreter's own input and output
+    /// are left as they were.
+    pub fn replay(&mut self, recording: &Recording) -> InterpreterResult<String> {
+        self.load_program(&recording.source)?;
+        self.scripted_directions = recording.directions.iter().copied().collect();
+
+        let result = self.run_with_input_bytes(recording.input.clone());
+        self.scripted_directions.clear();
+
+        result.map(|output| String::from_utf8_lossy(&output).into_owned())
+    }
+
+    fn run_with_input_bytes(&mut self, input: Vec<u8>) -> InterpreterResult<Vec<u8>> {
+        let input = std::mem::replace(&mut self.input, Cursor::new(input));
+        let output = std::mem::replace(&mut self.output, Cursor::new(Vec::new()));
+
+        self.restore_grid();
+        self.restart();
+        let result = self.run();
+
+        self.input = input;
+        let output = std::mem::replace(&mut self.output, output);
+
+        result.map(|_| output.into_inner())
+    }
+}
+
+impl Interpreter<Cursor<Vec<u8>>, Cursor<Vec<u8>>, StdRng> {
+    /// Copies the interpreter at its current point of execution, e.g. to
+    /// explore every direction a `?` can take. The fork gets the grid, stack,
+    /// PC, direction, mode and settings along with a copy of the random number
+    /// generator, so it draws the same numbers until given another one with
+    /// [`Interpreter::set_rng`]. Its input and output start out empty, and
+    /// hooks, the step history and any recording are not copied.
+    pub fn fork(&self) -> Self {
+        let stack = Operands {
+            inner: self.stack.inner.clone_box(),
+            default: self.stack.default,
+            limit: self.stack.limit,
+            journal: None,
+            hook: None,
+        };
+        let history = History {
+            limit: self.history.limit,
+            ..History::default()
+        };
+
+        Interpreter {
+            stack,
+            initial_stack: self.initial_stack.clone(),
+            program: self.program.clone(),
+            original_program: self.original_program.clone(),
+            pc: self.pc,
+            direction: self.direction,
+            width: self.width,
+            height: self.height,
+            mode: self.mode,
+            input: Cursor::new(Vec::new()),
+            output: Cursor::new(Vec::new()),
+            gen: self.gen.clone(),
+            enabled: self.enabled,
+            history,
+            code_cells: self.code_cells.clone(),
+            self_modifications: self.self_modifications.clone(),
+            wrap_hook: None,
+            random_mode: self.random_mode,
+            random_index: self.random_index,
+            div_by_zero: self.div_by_zero,
+            start: self.start,
+            separator: self.separator,
+            reference_compat: self.reference_compat,
+            track_coverage: self.track_coverage,
+            visited_cells: self.visited_cells.clone(),
+            echo_input: self.echo_input,
+            collect_stats: self.collect_stats,
+            last_run_stats: self.last_run_stats,
+            halt_hook: None,
+            input_hook: None,
+            comment_prefix: self.comment_prefix.clone(),
+            wrap_mode: self.wrap_mode,
+            oversize_behavior: self.oversize_behavior,
+            fast_path: self.fast_path,
+            fast_runs: self.fast_runs.clone(),
+            fast_cells: self.fast_cells.clone(),
+            stack_watches: self.stack_watches.clone(),
+            input_mode: self.input_mode,
+            string_mode: self.string_mode,
+            step_limit: self.step_limit,
+            last_halt_reason: self.last_halt_reason,
+            profile: self.profile,
+            cell_counts: self.cell_counts.clone(),
+            halt_on_broken_pipe: self.halt_on_broken_pipe,
+            output_limit: self.output_limit,
+            output_limit_behavior: self.output_limit_behavior,
+            output_written: 0,
+            input_limit: self.input_limit,
+            input_reads: self.input_reads,
+            recording: None,
+            scripted_directions: self.scripted_directions.clone(),
+        }
+    }
+}
+
+impl<R: BufRead, G: Rng> Interpreter<R, Cursor<Vec<u8>>, G> {
+    /// Returns everything written to the output so far.
+    pub fn output_bytes(&self) -> &[u8] {
+        self.output.get_ref()
+    }
+
+    /// Runs the program and collects everything written to the output along
+    /// with the final stack (bottom to top).
+    pub fn run_and_collect(&mut self) -> InterpreterResult<RunResult> {
+        self.run()?;
+
+        let output = self.output_bytes().to_vec();
+        let stack = self.stack.values().to_vec();
+        let halted = !self.enabled;
+
+        Ok(RunResult {
+            output,
+            stack,
+            halted,
+        })
+    }
+}
+
+impl<R: BufRead, W: Write, G: Rng> fmt::Display for Interpreter<R, W, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+impl Default for Interpreter<StdinLock<'static>, Stdout, ThreadRng> {
+    fn default() -> Self {
+        Self::new(io::stdin().lock(), io::stdout(), rand::thread_rng())
+    }
+}
+
+impl Interpreter<StdinLock<'static>, Stdout, ThreadRng> {
+    /// Creates an interpreter reading from stdin and writing to stdout with the
+    /// given program already loaded.
+    ///
+    /// ```
+    /// use befunge93_rs::{Interpreter, InterpreterError};
+    ///
+    /// let mut interpreter = Interpreter::from_source("@")?;
+    /// interpreter.run()?;
+    /// # Ok::<(), InterpreterError>(())
+    /// ```
+    pub fn from_source(source: &str) -> InterpreterResult<Self> {
+        let mut interpreter = Self::default();
+        interpreter.load_program(source)?;
+
+        Ok(interpreter)
+    }
+}
+
+impl Interpreter<Cursor<Vec<u8>>, Cursor<Vec<u8>>, ThreadRng> {
+    /// Runs `source` with empty input and checks whether it prints itself,
+    /// ignoring surrounding whitespace. Programs still running after a
+    /// million steps are not considered quines.
+    pub fn is_quine(source: &str) -> InterpreterResult<bool> {
+        Self::is_quine_within(source, QUINE_STEP_LIMIT)
+    }
+
+    fn is_quine_within(source: &str, max_steps: usize) -> InterpreterResult<bool> {
+        let input = Cursor::new(Vec::new());
+        let output = Cursor::new(Vec::new());
+        let mut interpreter = Interpreter::new(input, output, rand::thread_rng());
+        interpreter.load_program(source)?;
+
+        for _ in 0..max_steps {
+            if !interpreter.enabled {
+                break;
+            }
+
+            interpreter.step()?;
+        }
+
+        Ok(!interpreter.enabled && interpreter.output_bytes() == source.trim().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use io::Cursor;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    pub(crate) fn build_interpreter() -> Interpreter<Cursor<Vec<u8>>, Cursor<Vec<u8>>, StdRng> {
+        build_interpreter_with_input(Cursor::new(Vec::new()))
+    }
+
+    pub(crate) fn build_interpreter_with_input<R: BufRead>(
+        input: R,
+    ) -> Interpreter<R, Cursor<Vec<u8>>, StdRng> {
+        let output = Cursor::new(Vec::new());
+        let gen = StdRng::seed_from_u64(123);
+
+        Interpreter::new(input, output, gen)
+    }
+
+    #[test]
+    fn test_direction_reverse() {
+        assert_eq!(Direction::Left.reverse(), Direction::Right);
+        assert_eq!(Direction::Right.reverse(), Direction::Left);
+        assert_eq!(Direction::Up.reverse(), Direction::Down);
+        assert_eq!(Direction::Down.reverse(), Direction::Up);
+    }
+
+    #[test]
+    fn test_stack_pop_checked() {
+        let mut stack = Operands::new(0, Box::new(Stack::default()));
+        stack.push(0).unwrap();
+
+        assert_eq!(stack.pop_checked(), Some(0));
+        assert_eq!(stack.pop_checked(), None);
+    }
+
+    #[test]
+    fn test_stack_pop_on_empty_stack_returns_default() {
+        let mut stack = Operands::new(-1, Box::new(Stack::default()));
+
+        assert_eq!(stack.pop(), -1);
+        assert!(stack.values().is_empty());
+    }
+
+    #[test]
+    fn test_queue_pop_checked() {
+        let mut stack = Operands::new(0, Box::new(Queue::default()));
+        stack.push(1).unwrap();
+        stack.push(2).unwrap();
+
+        assert_eq!(stack.pop_checked(), Some(1));
+        assert_eq!(stack.pop_checked(), Some(2));
+        assert_eq!(stack.pop_checked(), None);
+    }
+
+    #[test]
+    fn test_queue_values_after_pops() {
+        let mut queue = Queue::default();
+        for v in 0..10 {
+            queue.push(v);
+        }
+        for v in 0..7 {
+            assert_eq!(queue.pop(), v);
+        }
+        queue.push(10);
+
+        assert_eq!(queue.values(), &[7, 8, 9, 10]);
+        assert_eq!(queue.next_index(), 0);
+
+        queue.truncate(2);
+        assert_eq!(queue.values(), &[7, 8]);
+    }
+
+    #[test]
+    fn test_queue_step_back() {
+        let input = Cursor::new(Vec::new());
+        let output = Cursor::new(Vec::new());
+        let gen = StdRng::seed_from_u64(123);
+        let mut interpreter = Interpreter::with_storage(input, output, gen, Queue::default());
+        interpreter.set_history_limit(10);
+        interpreter.load_program("123\\@").unwrap();
+
+        for _ in 0..4 {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(interpreter.get_stack(), &[3, 1, 2]);
+
+        interpreter.step_back().unwrap();
+        assert_eq!(interpreter.get_stack(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_stack_limit() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_stack_limit(5);
+        interpreter.load_program("1").unwrap();
+
+        let result = interpreter.run();
+
+        assert!(matches!(
+            result,
+            Err(InterpreterError::StackOverflow { limit: 5 })
+        ));
+        assert_eq!(interpreter.get_stack(), &[1; 5]);
+    }
+
+    #[test]
+    fn test_stack_limit_duplicate_on_empty_stack() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_stack_limit(1);
+        interpreter.load_program(":@").unwrap();
+
+        let result = interpreter.run();
+
+        assert!(matches!(
+            result,
+            Err(InterpreterError::StackOverflow { limit: 1 })
+        ));
+        assert!(interpreter.get_stack().is_empty());
+    }
+
+    #[test]
+    fn test_space_in_string_mode() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("\"A B\"@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_stack(), &[65, 32, 66]);
+    }
+
+    #[test]
+    fn test_consecutive_spaces_in_string_mode() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("\"A   B\"@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_stack(), &[65, 32, 32, 32, 66]);
+    }
+
+    #[test]
+    fn test_space_in_normal_mode() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1   2@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_stack(), &[1, 2]);
+    }
+
+    #[test]
+    fn test_string_mode_byte() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("\"éż\"@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_stack(), &[0xe9, 0x7c]);
+    }
+
+    #[test]
+    fn test_string_mode_unicode() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_string_mode(StringMode::Unicode);
+        interpreter.load_program("\"éż\"@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_stack(), &[0xe9, 0x17c]);
+    }
+
+    #[test]
+    fn test_string_mode_ascii() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_string_mode(StringMode::Ascii);
+        interpreter.load_program("\"aé\"@").unwrap();
+
+        let result = interpreter.run();
+
+        assert!(matches!(
+            result,
+            Err(InterpreterError::NonAsciiInString {
+                x: 2,
+                y: 0,
+                character: 'é'
+            })
+        ));
+        assert_eq!(interpreter.get_stack(), &[97]);
+    }
+
+    #[test]
+    fn test_save_and_load_stack() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("901-5@").unwrap();
+        interpreter.run().unwrap();
+        let mut saved = Vec::new();
+
+        interpreter.save_stack(&mut saved).unwrap();
+        let mut other = build_interpreter();
+        other.load_stack_from(&mut saved.as_slice()).unwrap();
+
+        assert_eq!(saved, b"9\n-1\n5\n");
+        assert_eq!(other.get_stack(), &[9, -1, 5]);
+    }
+
+    #[test]
+    fn test_load_stack_with_invalid_line() {
+        let mut interpreter = build_interpreter();
+
+        let result = interpreter.load_stack_from(&mut "1\nx\n".as_bytes());
+
+        assert!(matches!(result, Err(InterpreterError::ParseError(_))));
+    }
+
+    #[test]
+    fn test_with_stack() {
+        let input = Cursor::new(Vec::new());
+        let output = Cursor::new(Vec::new());
+        let gen = StdRng::seed_from_u64(123);
+        let mut interpreter = Interpreter::with_stack(input, output, gen, vec![2, 3]);
+        interpreter.load_program("+@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_stack(), &[5]);
+    }
+
+    #[test]
+    fn test_with_stack_order() {
+        let input = Cursor::new(Vec::new());
+        let output = Cursor::new(Vec::new());
+        let gen = StdRng::seed_from_u64(123);
+        let mut interpreter = Interpreter::with_stack(input, output, gen, vec![2, 3]);
+        assert_eq!(interpreter.get_stack(), &[2, 3]);
+        interpreter.load_program("-@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_stack(), &[-1]);
+    }
+
+    #[test]
+    fn test_stack_hook() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let events_ = Rc::clone(&events);
+        let mut interpreter = build_interpreter();
+        interpreter.set_stack_hook(move |event| events_.borrow_mut().push(event));
+        interpreter.load_program("12+@").unwrap();
+
+        interpreter.run().unwrap();
+
+        let expected = [
+            StackEvent::Push(1),
+            StackEvent::Push(2),
+            StackEvent::Pop(2),
+            StackEvent::Pop(1),
+            StackEvent::Push(3),
+        ];
+        assert_eq!(*events.borrow(), expected);
+    }
+
+    #[test]
+    fn test_input_hook() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let events_ = Rc::clone(&events);
+        let mut interpreter = build_interpreter();
+        interpreter.set_input_hook(move |event| events_.borrow_mut().push(event));
+        interpreter.load_program("~~@").unwrap();
+
+        interpreter.run_with_input("AB").unwrap();
+
+        let expected = [
+            InputEvent {
+                pc: (0, 0),
+                bytes: b"A".to_vec(),
+                value: 65,
+            },
+            InputEvent {
+                pc: (1, 0),
+                bytes: b"B".to_vec(),
+                value: 66,
+            },
+        ];
+        assert_eq!(*events.borrow(), expected);
+    }
+
+    #[test]
+    fn test_input_hook_int() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let events_ = Rc::clone(&events);
+        let mut interpreter = build_interpreter();
+        interpreter.set_input_hook(move |event| events_.borrow_mut().push(event));
+        interpreter.load_program("&@").unwrap();
+
+        interpreter.run_with_input(" -12\nx").unwrap();
+
+        let expected = [InputEvent {
+            pc: (0, 0),
+            bytes: b" -12\n".to_vec(),
+            value: -12,
+        }];
+        assert_eq!(*events.borrow(), expected);
+    }
+
+    #[test]
+    fn test_stack_hook_swap() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let events_ = Rc::clone(&events);
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12\\@").unwrap();
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        interpreter.set_stack_hook(move |event| events_.borrow_mut().push(event));
+
+        interpreter.step().unwrap();
+
+        let expected = [
+            StackEvent::Pop(2),
+            StackEvent::Pop(1),
+            StackEvent::Push(2),
+            StackEvent::Push(1),
+        ];
+        assert_eq!(*events.borrow(), expected);
+        assert_eq!(interpreter.get_stack(), &[2, 1]);
+    }
+
+    #[test]
+    fn test_hello_world() {
+        let mut interpreter = build_interpreter();
+        let program = include_str!("../programs/hello-world.txt");
+        interpreter.load_program(program).unwrap();
+
+        interpreter.run().unwrap();
+
+        let x = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(x, "Hello World!");
+    }
+
+    #[test]
+    fn test_into_parts() {
+        let mut interpreter = build_interpreter();
+        let program = include_str!("../programs/hello-world.txt");
+        interpreter.load_program(program).unwrap();
+        interpreter.run().unwrap();
+
+        let (_, output, _) = interpreter.into_parts();
+
+        assert_eq!(output.into_inner(), b"Hello World!");
+    }
+
+    #[test]
+    fn test_factorial() {
+        let mut interpreter = build_interpreter();
+        interpreter.input.write_all("5\n".as_bytes()).unwrap();
+        interpreter.input.set_position(0);
+        let program = include_str!("../programs/factorial.txt");
+        interpreter.load_program(program).unwrap();
+
+        interpreter.run().unwrap();
+
+        let x = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(x, "120");
+    }
+
+    #[test]
+    fn test_last_run_stats() {
+        let mut interpreter = build_interpreter();
+        interpreter.input.write_all("5\n".as_bytes()).unwrap();
+        interpreter.input.set_position(0);
+        interpreter.set_collect_stats(true);
+        let program = include_str!("../programs/factorial.txt");
+        interpreter.load_program(program).unwrap();
+
+        interpreter.run().unwrap();
+
+        let stats = interpreter.last_run_stats().unwrap();
+        assert!(stats.steps > 0);
+    }
+
+    #[test]
+    fn test_quine() {
+        let mut interpreter = build_interpreter();
+        let program = include_str!("../programs/quine.txt");
+        interpreter.load_program(program).unwrap();
+
+        interpreter.run().unwrap();
+
+        let x = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(x, program.trim());
+    }
+
+    #[test]
+    fn test_fast_path_quine() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_fast_path(true);
+        let program = include_str!("../programs/quine.txt");
+        interpreter.load_program(program).unwrap();
+
+        interpreter.run().unwrap();
+
+        let x = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(x, program.trim());
+    }
+
+    #[test]
+    fn test_fast_path_matches_interpreted() {
+        let program = include_str!("../programs/factorial.txt");
+        let mut results = Vec::new();
+
+        for fast_path in [false, true] {
+            let mut interpreter = build_interpreter();
+            interpreter.input.write_all(b"7\n").unwrap();
+            interpreter.input.set_position(0);
+            interpreter.set_fast_path(fast_path);
+            interpreter.set_collect_stats(true);
+            interpreter.load_program(program).unwrap();
+
+            let result = interpreter.run_and_collect().unwrap();
+            let steps = interpreter.last_run_stats().unwrap().steps;
+            results.push((result, steps));
+        }
+
+        assert_eq!(results[0], results[1]);
+        assert_eq!(results[1].0.output, b"5040");
+    }
+
+    #[test]
+    fn test_fast_path_reads_each_input_once() {
+        let mut interpreter = build_interpreter();
+        interpreter.input.write_all(b"AB").unwrap();
+        interpreter.input.set_position(0);
+        interpreter.set_fast_path(true);
+        interpreter.load_program("~~@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_stack(), &[65, 66]);
+    }
+
+    #[test]
+    fn test_fast_path_invalidated_by_put() {
+        let mut interpreter = build_interpreter();
+        interpreter.input.write_all(b"x").unwrap();
+        interpreter.input.set_position(0);
+        interpreter.set_fast_path(true);
+        interpreter
+            .load_program(">9.      v\n^p03\"@\"$~<")
+            .unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.output_bytes(), b"99");
+    }
+
+    #[test]
+    fn test_fast_path_calls_halt_hook_on_wrap() {
+        let halted = Rc::new(RefCell::new(false));
+        let halted_ = halted.clone();
+        let mut interpreter = build_interpreter();
+        interpreter.set_fast_path(true);
+        interpreter.set_wrap_mode(WrapMode::Halt);
+        interpreter.set_halt_hook(move |_| *halted_.borrow_mut() = true);
+        interpreter.load_program("12").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.get_stack(), &[1, 2]);
+        assert!(*halted.borrow());
+    }
+
+    #[test]
+    fn test_fast_path_output_limit_halt() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_fast_path(true);
+        interpreter.set_output_limit(1);
+        interpreter.set_output_limit_behavior(OutputLimitBehavior::Halt);
+        interpreter.load_program("55+.1.@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.output_bytes(), b"");
+        assert!(interpreter.get_stack().is_empty());
+        assert_eq!(interpreter.last_halt_reason(), Some(HaltReason::Halted));
+    }
+
+    #[test]
+    fn test_fast_path_halt_on_broken_pipe() {
+        let input = Cursor::new(Vec::new());
+        let gen = StdRng::seed_from_u64(123);
+        let mut interpreter = Interpreter::new(input, BrokenPipe, gen);
+        interpreter.set_fast_path(true);
+        interpreter.set_halt_on_broken_pipe(true);
+        interpreter.load_program("12.3@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert!(!interpreter.get_enabled());
+        assert_eq!(interpreter.get_stack(), &[1]);
+        assert_eq!(interpreter.last_halt_reason(), Some(HaltReason::Halted));
+    }
+
+    #[test]
+    fn test_stack_watch() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("5:+2*@").unwrap();
+        interpreter.add_stack_watch(0);
+
+        let mut changes = Vec::new();
+        for _ in 0..5 {
+            interpreter.step().unwrap();
+            changes.push(interpreter.watch_changed());
+        }
+
+        assert_eq!(changes, [true, false, true, true, true]);
+    }
+
+    #[test]
+    fn test_stack_watch_below_top() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12\\3$@").unwrap();
+        interpreter.add_stack_watch(1);
+
+        let mut changes = Vec::new();
+        for _ in 0..5 {
+            interpreter.step().unwrap();
+            changes.push(interpreter.watch_changed());
+        }
+
+        assert_eq!(changes, [false, true, true, true, true]);
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let io_error = io::Error::new(io::ErrorKind::UnexpectedEof, "eof");
+        let parse_error = "x".parse::<isize>().unwrap_err();
+        let cases = [
+            (InterpreterError::IoError(io_error), "io_error"),
+            (
+                InterpreterError::UnknownInstruction('x'),
+                "unknown_instruction",
+            ),
+            (InterpreterError::InvalidAscii(-1), "invalid_ascii"),
+            (
+                InterpreterError::InvalidCoordinates { x: 0, y: 0 },
+                "invalid_coordinates",
+            ),
+            (InterpreterError::ParseError(parse_error), "parse_error"),
+            (InterpreterError::ProgramNotLoaded, "program_not_loaded"),
+            (
+                InterpreterError::DivisionByZero { x: 0, y: 0 },
+                "division_by_zero",
+            ),
+            (
+                InterpreterError::ProgramTooLarge {
+                    width: 81,
+                    height: 1,
+                },
+                "program_too_large",
+            ),
+            (
+                InterpreterError::StackOverflow { limit: 1 },
+                "stack_overflow",
+            ),
+            (
+                InterpreterError::NonAsciiInString {
+                    x: 0,
+                    y: 0,
+                    character: 'é',
+                },
+                "non_ascii_in_string",
+            ),
+            (
+                InterpreterError::OutputLimitExceeded { limit: 1 },
+                "output_limit_exceeded",
+            ),
+        ];
+
+        for (error, code) in cases {
+            assert_eq!(error.code(), code);
+        }
+    }
+
+    #[test]
+    fn test_set_rng_boxed() {
+        let input = Cursor::new(Vec::new());
+        let output = Cursor::new(Vec::new());
+        let gen: Box<dyn RngCore> = Box::new(rand::thread_rng());
+        let mut boxed = Interpreter::new(input, output, gen);
+        boxed.load_program("?").unwrap();
+        let mut seeded = build_interpreter();
+        seeded.load_program("?").unwrap();
+
+        boxed.set_rng(Box::new(StdRng::seed_from_u64(123)));
+        for _ in 0..20 {
+            boxed.step().unwrap();
+            seeded.step().unwrap();
+            assert_eq!(boxed.direction, seeded.direction);
+        }
+    }
+
+    #[test]
+    fn test_run_with_input() {
+        let mut interpreter = build_interpreter();
+        let program = include_str!("../programs/factorial.txt");
+        interpreter.load_program(program).unwrap();
+
+        assert_eq!(interpreter.run_with_input("5\n").unwrap(), b"120");
+        assert_eq!(interpreter.run_with_input("4\n").unwrap(), b"24");
+        assert!(interpreter.output_bytes().is_empty());
+    }
+
+    #[test]
+    fn test_run_with_input_undoes_put() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_fast_path(true);
+        interpreter.load_program("1.\"2\"00p@").unwrap();
+
+        assert_eq!(interpreter.run_with_input("").unwrap(), b"1");
+        assert_eq!(interpreter.run_with_input("").unwrap(), b"1");
+        assert_eq!(interpreter.program[0][0], '2');
+    }
+
+    #[test]
+    fn test_run_with_input_keeps_reader() {
+        let mut interpreter = build_interpreter();
+        interpreter.input.write_all(b"A").unwrap();
+        interpreter.input.set_position(0);
+        interpreter.load_program("~,@").unwrap();
+
+        interpreter.run_with_input("B").unwrap();
+        let err = interpreter.run_with_input("").unwrap_err();
+
+        assert!(matches!(err, InterpreterError::IoError(_)));
+        assert_eq!(interpreter.get_input().position(), 0);
+        assert_eq!(interpreter.get_input().get_ref(), b"A");
+    }
+
+    #[test]
+    fn test_reset_with_rng() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("?1.@").unwrap();
+
+        let sample = |interpreter: &mut Interpreter<_, _, _>| {
+            interpreter.reset_with_rng(StdRng::seed_from_u64(7));
+            (0..32)
+                .map(|_| interpreter.run_with_input("").unwrap())
+                .collect::<Vec<_>>()
+        };
+        let first = sample(&mut interpreter);
+        let second = sample(&mut interpreter);
+
+        assert_eq!(first, second);
+        assert!(first.iter().any(|output| output.is_empty()));
+        assert!(first.iter().any(|output| output == b"1"));
+    }
+
+    #[test]
+    fn test_reset_with_rng_restores_grid() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_track_coverage(true);
+        interpreter.set_profile(true);
+        interpreter.load_program("88*1+00p@").unwrap();
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.get_cell(0, 0), Some('A'));
+        assert_eq!(interpreter.self_modifications(), &[(0, 0)]);
+
+        interpreter.reset_with_rng(StdRng::seed_from_u64(7));
+
+        assert_eq!(interpreter.get_cell(0, 0), Some('8'));
+        assert!(interpreter.grid_diff().is_empty());
+        assert!(interpreter.self_modifications().is_empty());
+        assert!(interpreter.visited_cells().is_empty());
+        assert!(interpreter.cell_hotspots().is_empty());
+        assert!(interpreter.get_enabled());
+        assert_eq!(interpreter.pc(), (0, 0));
+    }
+
+    #[test]
+    fn test_fork() {
+        let input = Cursor::new(Vec::new());
+        let output = Cursor::new(Vec::new());
+        let gen = StdRng::seed_from_u64(123);
+        let mut interpreter = Interpreter::with_stack(input, output, gen, vec![5]);
+        interpreter.set_random_mode(RandomMode::RoundRobin);
+        interpreter.set_stack_default(7);
+        interpreter.load_program("@.2?3...@").unwrap();
+        interpreter.set_start(3, 0, Direction::Right).unwrap();
+
+        // Both copies stand on the `?`. The original lets it pick left, the
+        // fork is sent right.
+        let mut fork = interpreter.fork();
+        fork.set_start(4, 0, Direction::Right).unwrap();
+        interpreter.run().unwrap();
+        fork.run().unwrap();
+
+        assert_eq!(interpreter.output_bytes(), b"2");
+        assert_eq!(interpreter.get_stack(), &[5]);
+        assert_eq!(fork.output_bytes(), b"357");
+        assert!(fork.get_stack().is_empty());
+        assert!(!fork.get_enabled());
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_random_mode(RandomMode::RoundRobin);
+        interpreter.record();
+        interpreter.load_program("~,~,?@").unwrap();
+
+        let output = interpreter.run_with_input("abcde").unwrap();
+        assert_eq!(output, b"ab\0c");
+
+        let recording = interpreter.recording().unwrap().clone();
+        assert_eq!(recording.source, "~,~,?@");
+        assert_eq!(recording.input, b"abcd");
+        assert_eq!(recording.directions, [Direction::Left]);
+
+        let mut replayer = build_interpreter();
+        assert_eq!(replayer.replay(&recording).unwrap(), "ab\0c");
+    }
+
+    #[test]
+    fn test_record_and_replay_random() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("~,~,?@").unwrap();
+        interpreter.record();
+
+        let output = interpreter.run_with_input("abcd").unwrap();
+        let recording = interpreter.recording().unwrap().clone();
+
+        assert!(!recording.directions.is_empty());
+        let mut replayer = build_interpreter();
+        assert_eq!(
+            replayer.replay(&recording).unwrap().as_bytes(),
+            output.as_slice()
+        );
+    }
+
+    #[test]
+    fn test_replay_keeps_settings() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_separator(Some(b' '));
+        interpreter.set_input_limit(1);
+        interpreter.load_program("~.~.@").unwrap();
+        interpreter.record();
+
+        let output = interpreter.run_with_input("AB").unwrap();
+        let recording = interpreter.recording().unwrap().clone();
+
+        assert_eq!(output, b"65 -1 ");
+        assert_eq!(recording.input, b"A");
+        assert_eq!(interpreter.replay(&recording).unwrap(), "65 -1 ");
+        assert!(interpreter.output_bytes().is_empty());
+    }
+
+    #[test]
+    fn test_is_quine() {
+        let program = include_str!("../programs/quine.txt");
+
+        assert!(Interpreter::is_quine(program).unwrap());
+        assert!(!Interpreter::is_quine("\"@\",@").unwrap());
+        assert!(!Interpreter::is_quine(">").unwrap());
+    }
+
+    #[test]
+    fn test_is_quine_halting_on_last_step() {
+        let program = include_str!("../programs/quine.txt");
+        let mut interpreter = build_interpreter();
+        interpreter.set_collect_stats(true);
+        interpreter.load_program(program).unwrap();
+        interpreter.run().unwrap();
+        let steps = interpreter.last_run_stats().unwrap().steps as usize;
+
+        assert!(Interpreter::is_quine_within(program, steps).unwrap());
+        assert!(!Interpreter::is_quine_within(program, steps - 1).unwrap());
+    }
+
+    #[test]
+    fn test_current_instruction() {
+        let mut interpreter = build_interpreter();
+        assert_eq!(interpreter.current_instruction(), None);
+
+        interpreter.load_program("12+@").unwrap();
+        assert_eq!(interpreter.current_instruction(), Some('1'));
+
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.current_instruction(), Some('2'));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let mut interpreter = build_interpreter();
+        let program = include_str!("../programs/factorial.txt");
+        interpreter.load_program(program).unwrap();
+
+        let stats = interpreter.analyze();
+
+        assert!(stats.reads_input);
+        assert!(stats.halts);
+        assert!(!stats.self_modifying);
+        assert_eq!(stats.instructions[&':'], 3);
+        assert_eq!(stats.instructions.get(&' '), None);
+        assert_eq!(interpreter.get_stack(), &[]);
+    }
+
+    #[test]
+    fn test_load_program_bytes() {
+        let mut interpreter = build_interpreter();
+        interpreter
+            .load_program_bytes(b"01g.@\r\n\xc0\xff\n")
+            .unwrap();
+
+        assert_eq!(interpreter.get_cell(0, 1), Some('\u{c0}'));
+        assert_eq!(interpreter.get_cell(1, 1), Some('\u{ff}'));
+        assert_eq!(interpreter.get_cell(0, 2), None);
+
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.output_bytes(), b"192");
+    }
+
+    #[test]
+    fn test_run_steps_with_callback() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12+@").unwrap();
+
+        let mut stacks = Vec::new();
+        let outcome = interpreter
+            .run_steps_with_callback(2, |interpreter| {
+                stacks.push(interpreter.get_stack().to_vec())
+            })
+            .unwrap();
+        assert_eq!(outcome, StepOutcome::Running);
+        assert_eq!(stacks, [vec![1], vec![1, 2]]);
+
+        let mut calls = 0;
+        let outcome = interpreter
+            .run_steps_with_callback(10, |_| calls += 1)
+            .unwrap();
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(calls, 2);
+        assert_eq!(interpreter.get_stack(), &[3]);
+    }
+
+    #[test]
+    fn test_run_steps_with_callback_counts_steps() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12+@").unwrap();
+
+        let mut calls = 0;
+        let outcome = interpreter
+            .run_steps_with_callback(100, |_| calls += 1)
+            .unwrap();
+
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(calls, 4);
+    }
+
+    #[test]
+    fn test_step_yielding_interleaved() {
+        let mut a = build_interpreter();
+        a.load_program("\"AB\",,@").unwrap();
+        let mut b = build_interpreter();
+        b.load_program("#5.@").unwrap();
+
+        let mut steps = [0, 0];
+        let mut outcomes = [StepOutcome::Running; 2];
+        while outcomes.contains(&StepOutcome::Running) {
+            for (k, interpreter) in [&mut a, &mut b].into_iter().enumerate() {
+                if outcomes[k] == StepOutcome::Running {
+                    outcomes[k] = interpreter.step_yielding().unwrap();
+                    steps[k] += 1;
+                }
+            }
+        }
+
+        assert_eq!(steps, [7, 3]);
+        assert_eq!(a.output_bytes(), b"BA");
+        assert_eq!(b.output_bytes(), b"0");
+        assert_eq!(a.step_yielding().unwrap(), StepOutcome::Halted);
+    }
+
+    #[test]
+    fn test_mode_at_pc_heuristic() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("\"abc\"@").unwrap();
+        assert_eq!(interpreter.mode_at_pc_heuristic(), None);
+
+        interpreter.set_start(2, 0, Direction::Right).unwrap();
+        assert_eq!(interpreter.mode_at_pc_heuristic(), Some(Mode::String));
+
+        interpreter.set_start(2, 0, Direction::Left).unwrap();
+        assert_eq!(interpreter.mode_at_pc_heuristic(), Some(Mode::String));
+
+        interpreter.set_start(5, 0, Direction::Right).unwrap();
+        assert_eq!(interpreter.mode_at_pc_heuristic(), None);
+
+        interpreter.set_start(0, 0, Direction::Right).unwrap();
+        interpreter.load_program("\"\na\n\"").unwrap();
+        interpreter.set_start(0, 1, Direction::Up).unwrap();
+        assert_eq!(interpreter.mode_at_pc_heuristic(), Some(Mode::String));
+    }
+
+    #[test]
+    fn test_halt_cells() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("v @\n>1_@").unwrap();
+
+        assert_eq!(interpreter.halt_cells(), vec![(2, 0), (3, 1)]);
+
+        interpreter.load_program(">").unwrap();
+        assert!(interpreter.halt_cells().is_empty());
+    }
+
+    #[test]
+    fn test_static_termination_check() {
+        let mut interpreter = build_interpreter();
+
+        interpreter.load_program(">1.").unwrap();
+        assert_eq!(
+            interpreter.static_termination_check(),
+            TerminationHint::NoHalt
+        );
+
+        interpreter.load_program("\"@\",").unwrap();
+        assert_eq!(
+            interpreter.static_termination_check(),
+            TerminationHint::NoHalt
+        );
+
+        interpreter.load_program("\"@\",\nv\"\"@").unwrap();
+        assert_eq!(
+            interpreter.static_termination_check(),
+            TerminationHint::HasHalt
+        );
+    }
+
+    #[test]
+    fn test_halt_reason() {
+        let mut interpreter = build_interpreter();
+        assert_eq!(interpreter.last_halt_reason(), None);
+
+        interpreter.load_program("12+@").unwrap();
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.last_halt_reason(), Some(HaltReason::Halted));
+        assert_eq!(interpreter.pc(), (0, 0));
+
+        interpreter.load_program("x").unwrap();
+        interpreter.run().unwrap_err();
+        assert_eq!(interpreter.last_halt_reason(), Some(HaltReason::Errored));
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_step_limit(Some(3));
+        interpreter.load_program("1").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.last_halt_reason(), Some(HaltReason::StepLimit));
+        assert_eq!(interpreter.get_stack(), &[1, 1, 1]);
+    }
+
+    #[test]
+    fn test_remove_step_limit() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_step_limit(Some(3));
+        interpreter.set_step_limit(None);
+        interpreter.load_program("1111@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.last_halt_reason(), Some(HaltReason::Halted));
+        assert_eq!(interpreter.get_stack(), &[1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_cell_hotspots() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_profile(true);
+        interpreter.load_program("9>1-:v\n@^   _").unwrap();
+
+        interpreter.run().unwrap();
+
+        let hotspots = interpreter.cell_hotspots();
+        assert_eq!(hotspots[0], ((1, 0), 9));
+        let counts: Vec<_> = hotspots.iter().map(|&(_, count)| count).collect();
+        assert_eq!(counts, [9, 9, 9, 9, 9, 9, 8, 8, 8, 8, 1, 1]);
+        assert_eq!(hotspots[10..], [((0, 0), 1), ((0, 1), 1)]);
+    }
+
+    #[test]
+    fn test_get_cell() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12\n3").unwrap();
+
+        assert_eq!(interpreter.get_cell(1, 0), Some('2'));
+        assert_eq!(interpreter.get_cell(1, 1), Some(' '));
+        assert_eq!(interpreter.get_cell(2, 0), None);
+    }
+
+    #[test]
+    fn test_run_and_collect() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("\"!iH\",,,9@").unwrap();
+
+        let result = interpreter.run_and_collect().unwrap();
+
+        let expected = RunResult {
+            output: b"Hi!".to_vec(),
+            stack: vec![9],
+            halted: true,
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_load_program_with_trailing_newline() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("@\n").unwrap();
+
+        assert_eq!(interpreter.height, 1);
+    }
+
+    #[test]
+    fn test_load_program_with_trailing_blank_line() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("\"A\"01p@\n\n").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.height, 2);
+        assert_eq!(interpreter.program[1][0], 'A');
+    }
+
+    #[test]
+    fn test_load_program_pads_ragged_rows() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1\n123\n12").unwrap();
+
+        assert!(interpreter.validate_grid());
+        assert!(interpreter.program.iter().all(|row| row.len() == 3));
+    }
+
+    #[test]
+    fn test_validate_grid_with_ragged_rows() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("123\n123").unwrap();
+        interpreter.program[1].pop();
+
+        assert!(!interpreter.validate_grid());
+    }
+
+    #[test]
+    fn test_validate_program() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1.@\n \x07").unwrap();
+
+        let result = interpreter.validate_program();
+
+        assert_eq!(result, Err(vec![(1, 1, '\x07')]));
+    }
+
+    #[test]
+    fn test_load_program_with_comment_prefix() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_comment_prefix(Some(";"));
+        interpreter
+            .load_program("; pushes one\nv\n; and halts\n1\n@")
+            .unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.height, 3);
+        assert_eq!(interpreter.width, 1);
+        assert_eq!(interpreter.stack.values(), vec![1]);
+    }
+
+    #[test]
+    fn test_load_program_with_empty_comment_prefix() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_comment_prefix(Some(""));
+        interpreter.load_program("1@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.width, 2);
+        assert_eq!(interpreter.stack.values(), vec![1]);
+    }
+
+    #[test]
+    fn test_load_grid() {
+        let mut interpreter = build_interpreter();
+        let grid = vec![vec!['1', ' ', ' '], vec!['@']];
+        interpreter.load_grid(grid).unwrap();
+
+        assert_eq!(interpreter.width, 3);
+        assert_eq!(interpreter.height, 2);
+        assert_eq!(interpreter.program[1], vec!['@', ' ', ' ']);
+        assert!(interpreter.validate_grid());
+
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.pc, (0, 0));
+    }
+
+    #[test]
+    fn test_load_program_truncates_oversized_program() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_oversize_behavior(OversizeBehavior::Truncate);
+        let program = format!("{}\n", "1".repeat(90)).repeat(30);
+        interpreter.load_program(&program).unwrap();
+
+        assert_eq!(interpreter.height, 25);
+        assert_eq!(interpreter.width, 80);
+        assert!(interpreter.validate_grid());
+    }
+
+    #[test]
+    fn test_load_program_rejects_oversized_program() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_oversize_behavior(OversizeBehavior::Error);
+        let program = "@\n".repeat(30);
+
+        let result = interpreter.load_program(&program);
+
+        assert!(matches!(
+            result,
+            Err(InterpreterError::ProgramTooLarge {
+                width: 1,
+                height: 30
+            })
+        ));
+    }
+
+    #[test]
+    fn test_load_program_allows_oversized_program() {
+        let mut interpreter = build_interpreter();
+        let program = "@\n".repeat(30);
+        interpreter.load_program(&program).unwrap();
+
+        assert_eq!(interpreter.height, 30);
+    }
+
+    #[test]
+    fn test_push_digit_to_stack() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1").unwrap();
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_add_instruction() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12+@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 3);
+    }
+
+    #[test]
+    fn test_subtract_instruction() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12-@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), -1);
+    }
+
+    #[test]
+    fn test_multiply_instruction() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("34*@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 12);
+    }
+
+    #[test]
+    fn test_divide_instruction_with_non_zero_denominator() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("72/@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 3);
+    }
+
+    #[test]
+    fn test_divide_instruction_with_zero_denominator() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("70/@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 0);
+    }
+
+    #[test]
+    fn test_divide_instruction_with_zero_denominator_and_error_policy() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_div_by_zero(DivByZero::Error);
+        interpreter.load_program("70/@").unwrap();
+
+        let result = interpreter.run();
+
+        assert!(matches!(
+            result,
+            Err(InterpreterError::DivisionByZero { x: 7, y: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_remainder_instruction_with_zero_denominator_and_error_policy() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_div_by_zero(DivByZero::Error);
+        interpreter.load_program("70%@").unwrap();
+
+        let result = interpreter.run();
+
+        assert!(matches!(
+            result,
+            Err(InterpreterError::DivisionByZero { x: 7, y: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_remainder_instruction() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("052%@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_logical_not_when_top_of_the_stack_is_zero() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("0!@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_logical_not_when_top_of_the_stack_is_non_zero() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("5!%@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 0);
+    }
+
+    #[test]
+    fn test_greater_than_when_top_of_the_stack_is_greater_than_next() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("25`@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 0);
+    }
+
+    #[test]
+    fn test_greater_than_when_top_of_the_stack_is_lesser_than_next() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("52`@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_greater_than_when_top_of_the_stack_is_lesser_equal_to_next() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("52`@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_start_moving_right() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("v  \n>1@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_start_moving_left() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("<@1").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_start_moving_up() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("^\n@\n1").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_start_moving_down() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("v\n1\n@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_start_moving_randomly() {
+        let input = Cursor::new(Vec::new());
+        let output = Cursor::new(Vec::new());
+        let gen = StdRng::seed_from_u64(123);
+        let mut interpreter = Interpreter::new(input, output, gen);
+        interpreter.load_program("?\n@\n1").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_start_moving_round_robin() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_random_mode(RandomMode::RoundRobin);
+        interpreter.load_program("?").unwrap();
+
+        let mut directions = Vec::new();
+        for _ in 0..5 {
+            interpreter.step().unwrap();
+            directions.push(interpreter.direction);
+        }
+
+        assert_eq!(
+            directions,
+            vec![
+                Direction::Left,
+                Direction::Right,
+                Direction::Up,
+                Direction::Down,
+                Direction::Left
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_start() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("@\n1@").unwrap();
+        interpreter.set_start(0, 1, Direction::Left).unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.values(), vec![1]);
+    }
+
+    #[test]
+    fn test_set_start_before_loading() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_start(0, 1, Direction::Left).unwrap();
+        interpreter.load_program("@\n1@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.values(), vec![1]);
+    }
+
+    #[test]
+    fn test_set_start_with_invalid_coordinates() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("@\n1@").unwrap();
+
+        let result = interpreter.set_start(2, 1, Direction::Left);
+
+        assert!(matches!(
+            result,
+            Err(InterpreterError::InvalidCoordinates { x: 2, y: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_load_program_with_start_outside() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12345@").unwrap();
+        interpreter.set_start(5, 0, Direction::Right).unwrap();
+
+        let result = interpreter.load_program("@");
+
+        assert!(matches!(
+            result,
+            Err(InterpreterError::InvalidCoordinates { x: 5, y: 0 })
+        ));
+        interpreter.step().unwrap();
+        assert!(!interpreter.get_enabled());
+    }
+
+    #[test]
+    fn test_horizontal_if_when_0() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("v\n0\n_1@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_horizontal_if_when_other_than_0() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("v\n1\n_@1").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_vertical_if_when_0() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("0|\n 1\n @").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_vertical_if_when_other_than_0() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1|\n @\n 1").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_string_mode() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("\"HA\"@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 65);
+        assert_eq!(interpreter.stack.pop(), 72);
+    }
+
+    #[test]
+    fn test_mode() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("\"A\"@").unwrap();
+
+        assert_eq!(interpreter.mode(), Mode::Normal);
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.mode(), Mode::String);
+    }
+
+    #[test]
+    fn test_duplicate_top_of_the_stack() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1:@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_swap_top_stack_values() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12\\@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.stack.pop(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_top_of_the_empty_stack() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program(":@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.values(), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_swap_top_stack_values_with_single_value() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1\\@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.values(), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_step_back_over_swap() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_history_limit(10);
+        interpreter.load_program("12\\@").unwrap();
+
+        interpreter.run().unwrap();
+        interpreter.step_back().unwrap();
+        interpreter.step_back().unwrap();
+
+        assert_eq!(interpreter.stack.values(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_queue_storage() {
+        let input = Cursor::new(Vec::new());
+        let output = Cursor::new(Vec::new());
+        let gen = StdRng::seed_from_u64(123);
+        let mut interpreter = Interpreter::with_storage(input, output, gen, Queue::default());
+        interpreter.load_program("12-.@").unwrap();
+
+        interpreter.run().unwrap();
+
+        let output = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(output, "1");
+    }
+
+    #[test]
+    fn test_stack_storage() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12-.@").unwrap();
+
+        interpreter.run().unwrap();
+
+        let output = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(output, "-1");
+    }
+
+    #[test]
+    fn test_stack_default() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_stack_default(-1);
+        interpreter.load_program("$2*.@").unwrap();
+
+        interpreter.run().unwrap();
+
+        let output = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(output, "-2");
+    }
+
+    #[test]
+    fn test_pop_and_discard() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1$@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert!(interpreter.stack.values().is_empty());
+    }
+
+    #[test]
+    fn test_pop_and_output_int() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("79*2+.@").unwrap();
+
+        interpreter.run().unwrap();
+
+        let output = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(output, "65");
+    }
+
+    #[test]
+    fn test_pop_and_output_int_with_separator() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_separator(Some(b'\n'));
+        interpreter.load_program("123...@").unwrap();
+
+        interpreter.run().unwrap();
+
+        let output = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(output, "3\n2\n1\n");
+    }
+
+    #[test]
+    fn test_pop_and_output_int_reference_compat() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_reference_compat(true);
+        interpreter.load_program("79*2+.1.@").unwrap();
+
+        interpreter.run().unwrap();
+
+        let output = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(output, "65 1 ");
+
+        interpreter.set_reference_compat(false);
+        assert_eq!(interpreter.run_with_input("").unwrap(), b"651");
+    }
+
+    #[test]
+    fn test_reference_compat_keeps_separator() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_separator(Some(b'\n'));
+        interpreter.set_reference_compat(true);
+        interpreter.set_reference_compat(false);
+        interpreter.load_program("1.@").unwrap();
+
+        assert_eq!(interpreter.run_with_input("").unwrap(), b"1\n");
+
+        interpreter.set_reference_compat(true);
+        assert_eq!(interpreter.run_with_input("").unwrap(), b"1 \n");
+    }
+
+    #[test]
+    fn test_pop_and_output_char_with_valid_ascii_code() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("79*2+,@").unwrap();
+
+        interpreter.run().unwrap();
+
+        let output = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(output, "A");
+    }
+
+    #[test]
+    fn test_pop_and_output_char_with_invalid_ascii_code() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12-,@").unwrap();
+
+        let result = interpreter.run();
+
+        match result {
+            Err(InterpreterError::InvalidAscii(-1)) => (),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn test_bridge() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1@1@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.stack.pop(), 1);
+    }
+
+    #[test]
+    fn test_bridge_at_right_edge() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1 #").unwrap();
+
+        for _ in 0..3 {
+            interpreter.step().unwrap();
+        }
+
+        assert_eq!(interpreter.pc, (0, 1));
+        assert_eq!(interpreter.stack.values(), vec![1]);
+    }
+
+    #[test]
+    fn test_bridge_at_bottom_edge() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("v\n \n#").unwrap();
+
+        for _ in 0..3 {
+            interpreter.step().unwrap();
+        }
+
+        assert_eq!(interpreter.pc, (1, 0));
+    }
+
+    #[test]
+    fn test_bridge_on_single_cell_grid() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("#").unwrap();
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.pc, (0, 0));
+    }
+
+    #[test]
+    fn test_bridge_on_single_row_grid() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("1#").unwrap();
+
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.pc, (0, 1));
+        assert_eq!(interpreter.stack.values(), vec![1]);
+    }
+
+    #[test]
+    fn test_bridge_on_single_column_grid() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("v\n#\n1\n@").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert!(interpreter.stack.values().is_empty());
+    }
+
+    #[test]
+    fn test_bridge_at_edge_torus() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_start(3, 0, Direction::Right).unwrap();
+        interpreter.load_program("@12#").unwrap();
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.pc(), (1, 0));
+        assert!(interpreter.get_enabled());
+    }
+
+    #[test]
+    fn test_bridge_at_edge_halt() {
+        let halts = Rc::new(RefCell::new(0));
+        let halts_ = Rc::clone(&halts);
+        let mut interpreter = build_interpreter();
+        interpreter.set_wrap_mode(WrapMode::Halt);
+        interpreter.set_halt_hook(move |_| *halts_.borrow_mut() += 1);
+        interpreter.set_start(3, 0, Direction::Right).unwrap();
+        interpreter.load_program("@12#").unwrap();
+
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.pc(), (3, 0));
+        assert!(!interpreter.get_enabled());
+        assert_eq!(*halts.borrow(), 1);
+    }
+
+    #[test]
+    fn test_bridge_over_edge_halt() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_wrap_mode(WrapMode::Halt);
+        interpreter.set_start(2, 0, Direction::Right).unwrap();
+        interpreter.load_program("@1#2").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.pc(), (3, 0));
+        assert!(interpreter.get_stack().is_empty());
+        assert_eq!(interpreter.last_halt_reason(), Some(HaltReason::Halted));
+    }
+
+    #[test]
+    fn test_wrap_hook() {
+        let mut interpreter = build_interpreter();
+        let wraps = Rc::new(RefCell::new(Vec::new()));
+        let wraps_ = Rc::clone(&wraps);
+        interpreter.set_wrap_hook(move |from, to, direction| {
+            wraps_.borrow_mut().push((from, to, direction));
+        });
+        interpreter.load_program("123").unwrap();
+
+        for _ in 0..6 {
+            interpreter.step().unwrap();
+        }
+
+        let expected = ((2, 0), (0, 0), Direction::Right);
+        assert_eq!(*wraps.borrow(), vec![expected, expected]);
+    }
+
+    #[test]
+    fn test_running_off_the_edge_in_torus_mode() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12").unwrap();
+
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+
+        assert!(interpreter.get_enabled());
+        assert_eq!(interpreter.pc, (0, 0));
+    }
+
+    #[test]
+    fn test_running_off_the_edge_in_halt_mode() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_wrap_mode(WrapMode::Halt);
+        interpreter.load_program("12").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert!(!interpreter.get_enabled());
+        assert_eq!(interpreter.pc, (0, 1));
+        assert_eq!(interpreter.stack.values(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_put_with_valid_ascii_code() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("79*2+21p@\n\n").unwrap();
+
+        interpreter.run().unwrap();
+
+        assert_eq!(interpreter.program[1][2], 'A');
+    }
+
+    #[test]
+    fn test_put_with_invalid_ascii_code() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("12-21p@\n\n").unwrap();
+
+        let result = interpreter.run();
+
+        match result {
+            Err(InterpreterError::InvalidAscii(-1)) => (),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn test_put_with_invalid_coordinates() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("79*2+89p@\n\n").unwrap();
+
+        let result = interpreter.run();
 
-        Interpreter::new(input, output, gen)
+        match result {
+            Err(InterpreterError::InvalidCoordinates { x: 8, y: 9 }) => (),
+            _ => assert!(false),
+        }
     }
 
     #[test]
-    fn test_hello_world() {
+    fn test_get() {
         let mut interpreter = build_interpreter();
-        let program = include_str!("../programs/hello-world.txt");
-        interpreter.load_program(program).unwrap();
+        interpreter.load_program("21g@\n  A").unwrap();
 
         interpreter.run().unwrap();
 
-        let x = String::from_utf8_lossy(interpreter.output.get_ref());
-        assert_eq!(x, "Hello World!");
+        assert_eq!(interpreter.stack.pop(), 65);
     }
 
     #[test]
-    fn test_factorial() {
+    fn test_get_with_invalid_coordinates() {
         let mut interpreter = build_interpreter();
-        interpreter.input.write_all("5\n".as_bytes()).unwrap();
-        interpreter.input.set_position(0);
-        let program = include_str!("../programs/factorial.txt");
-        interpreter.load_program(program).unwrap();
+        interpreter.load_program("89g@\n  A").unwrap();
 
-        interpreter.run().unwrap();
+        let result = interpreter.run();
 
-        let x = String::from_utf8_lossy(interpreter.output.get_ref());
-        assert_eq!(x, "120");
+        match result {
+            Err(InterpreterError::InvalidCoordinates { x: 8, y: 9 }) => (),
+            _ => assert!(false),
+        }
     }
 
     #[test]
-    fn test_quine() {
+    fn test_get_with_huge_coordinate() {
         let mut interpreter = build_interpreter();
-        let program = include_str!("../programs/quine.txt");
-        interpreter.load_program(program).unwrap();
+        interpreter.load_program("099999****g@").unwrap();
 
-        interpreter.run().unwrap();
+        let result = interpreter.run();
 
-        let x = String::from_utf8_lossy(interpreter.output.get_ref());
-        assert_eq!(x, program.trim());
+        assert!(matches!(
+            result,
+            Err(InterpreterError::InvalidCoordinates { x: 0, y: 59049 })
+        ));
     }
 
     #[test]
-    fn test_push_digit_to_stack() {
+    fn test_get_with_negative_coordinate() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("1").unwrap();
+        interpreter.load_program("01-0g@").unwrap();
 
-        interpreter.step().unwrap();
+        let result = interpreter.run();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert!(matches!(
+            result,
+            Err(InterpreterError::InvalidCoordinates { x: -1, y: 0 })
+        ));
     }
 
     #[test]
-    fn test_add_instruction() {
+    fn test_put_with_huge_coordinate() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("12+@").unwrap();
+        interpreter.load_program("1099999****p@").unwrap();
 
-        interpreter.run().unwrap();
+        let result = interpreter.run();
 
-        assert_eq!(interpreter.stack.pop(), 3);
+        assert!(matches!(
+            result,
+            Err(InterpreterError::InvalidCoordinates { x: 0, y: 59049 })
+        ));
     }
 
     #[test]
-    fn test_subtract_instruction() {
+    fn test_get_and_put_past_end_of_short_row() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("12-@").unwrap();
+        interpreter.load_program("v\n>99*40p40g50g@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), -1);
+        assert_eq!(interpreter.get_stack(), &[81, 32]);
     }
 
     #[test]
-    fn test_multiply_instruction() {
+    fn test_get_and_put_on_degenerate_grid() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("34*@").unwrap();
+        interpreter.load_program("\n\n").unwrap();
+        interpreter.resize_grid(0, 0);
+        assert!(matches!(
+            interpreter.step(),
+            Err(InterpreterError::ProgramNotLoaded)
+        ));
+
+        interpreter.stack.set_values(&[65, 0, 0]);
+        assert!(matches!(
+            interpreter.put(),
+            Err(InterpreterError::InvalidCoordinates { x: 0, y: 0 })
+        ));
+        interpreter.stack.set_values(&[0, 0]);
+        assert!(matches!(
+            interpreter.get(),
+            Err(InterpreterError::InvalidCoordinates { x: 0, y: 0 })
+        ));
+    }
 
-        interpreter.run().unwrap();
+    #[test]
+    fn test_get_beyond_width_on_short_row() {
+        let mut interpreter = build_interpreter();
+        interpreter.load_program("v\n>90g@").unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 12);
+        let result = interpreter.run();
+
+        assert!(matches!(
+            result,
+            Err(InterpreterError::InvalidCoordinates { x: 9, y: 0 })
+        ));
     }
 
     #[test]
-    fn test_divide_instruction_with_non_zero_denominator() {
+    fn test_put_beyond_height() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("72/@").unwrap();
+        interpreter.load_program("v\n>1002p@").unwrap();
 
-        interpreter.run().unwrap();
+        let result = interpreter.run();
 
-        assert_eq!(interpreter.stack.pop(), 3);
+        assert!(matches!(
+            result,
+            Err(InterpreterError::InvalidCoordinates { x: 0, y: 2 })
+        ));
     }
 
     #[test]
-    fn test_divide_instruction_with_zero_denominator() {
+    fn test_resize_grid() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("70/@").unwrap();
+        interpreter
+            .load_program("\"A\"99*2-46*p99*2-46*g@")
+            .unwrap();
 
+        interpreter.resize_grid(80, 25);
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 0);
+        assert_eq!(interpreter.get_stack(), &[65]);
+        assert_eq!(interpreter.render().lines().count(), 25);
+        assert_eq!(interpreter.render().lines().last().unwrap().len(), 80);
     }
 
     #[test]
-    fn test_remainder_instruction() {
+    fn test_resize_grid_never_shrinks() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("052%@").unwrap();
+        interpreter.load_program("12\n3@").unwrap();
 
-        interpreter.run().unwrap();
+        interpreter.resize_grid(1, 1);
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.render(), "[1]2\n3@");
     }
 
     #[test]
-    fn test_logical_not_when_top_of_the_stack_is_zero() {
+    fn test_grid_diff() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("0!@").unwrap();
+        interpreter.load_program("\"X\"70p@ 7").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.grid_diff(), [(7, 0, ' ', 'X')]);
     }
 
     #[test]
-    fn test_logical_not_when_top_of_the_stack_is_non_zero() {
+    fn test_grid_diff_after_resize() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("5!%@").unwrap();
+        interpreter.load_program("\"X\"92p@").unwrap();
+        interpreter.resize_grid(10, 3);
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 0);
+        assert_eq!(interpreter.grid_diff(), [(9, 2, ' ', 'X')]);
     }
 
     #[test]
-    fn test_greater_than_when_top_of_the_stack_is_greater_than_next() {
+    fn test_get_char_utf8() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("25`@").unwrap();
+        interpreter.input.write_all("żA".as_bytes()).unwrap();
+        interpreter.input.set_position(0);
+        interpreter.set_input_mode(InputMode::Utf8);
+        interpreter.load_program("~~@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 0);
+        assert_eq!(interpreter.get_stack(), &[0x17c, 65]);
     }
 
     #[test]
-    fn test_greater_than_when_top_of_the_stack_is_lesser_than_next() {
+    fn test_get_char_utf8_incomplete_at_eof() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("52`@").unwrap();
+        interpreter.input.write_all(&"€".as_bytes()[..2]).unwrap();
+        interpreter.input.set_position(0);
+        interpreter.set_input_mode(InputMode::Utf8);
+        interpreter.load_program("~@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.get_stack(), &[-1]);
     }
 
     #[test]
-    fn test_greater_than_when_top_of_the_stack_is_lesser_equal_to_next() {
+    fn test_get_char_utf8_invalid() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("52`@").unwrap();
+        interpreter.input.write_all(&[0xff, 0xc3, 0x28]).unwrap();
+        interpreter.input.set_position(0);
+        interpreter.set_input_mode(InputMode::Utf8);
+        interpreter.load_program("~~@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.get_stack(), &[0xfffd, 0xfffd]);
+    }
+
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
     }
 
     #[test]
-    fn test_start_moving_right() {
-        let mut interpreter = build_interpreter();
-        interpreter.load_program("v  \n>1@").unwrap();
+    fn test_broken_pipe_is_an_error_by_default() {
+        let input = Cursor::new(Vec::new());
+        let gen = StdRng::seed_from_u64(123);
+        let mut interpreter = Interpreter::new(input, BrokenPipe, gen);
+        interpreter.load_program("12.3@").unwrap();
+
+        let result = interpreter.run();
+
+        assert!(matches!(result, Err(InterpreterError::IoError(_))));
+    }
+
+    #[test]
+    fn test_halt_on_broken_pipe() {
+        let input = Cursor::new(Vec::new());
+        let gen = StdRng::seed_from_u64(123);
+        let mut interpreter = Interpreter::new(input, BrokenPipe, gen);
+        interpreter.set_halt_on_broken_pipe(true);
+        interpreter.load_program("12.3@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert!(!interpreter.get_enabled());
+        assert_eq!(interpreter.get_stack(), &[1]);
+        assert_eq!(interpreter.last_halt_reason(), Some(HaltReason::Halted));
     }
 
     #[test]
-    fn test_start_moving_left() {
+    fn test_output_limit() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("<@1").unwrap();
+        interpreter.set_output_limit(5);
+        interpreter.load_program("1.").unwrap();
 
-        interpreter.run().unwrap();
+        let result = interpreter.run();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert!(matches!(
+            result,
+            Err(InterpreterError::OutputLimitExceeded { limit: 5 })
+        ));
+        assert_eq!(interpreter.output_bytes(), b"11111");
     }
 
     #[test]
-    fn test_start_moving_up() {
+    fn test_output_limit_halt() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("^\n@\n1").unwrap();
+        interpreter.set_output_limit(5);
+        interpreter.set_output_limit_behavior(OutputLimitBehavior::Halt);
+        interpreter.set_separator(Some(b' '));
+        interpreter.load_program("1.").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.output_bytes(), b"1 1 ");
+        assert_eq!(interpreter.last_halt_reason(), Some(HaltReason::Halted));
+        assert_eq!(interpreter.run_with_input("").unwrap(), b"1 1 ");
     }
 
     #[test]
-    fn test_start_moving_down() {
+    fn test_input_limit() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("v\n1\n@").unwrap();
+        interpreter.set_input_limit(2);
+        interpreter.load_program("~&~&@").unwrap();
 
-        interpreter.run().unwrap();
+        interpreter.run_with_input("A12\nBC").unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.get_stack(), &[65, 12, -1, -1]);
+
+        interpreter.run_with_input("A12\n").unwrap();
+        assert_eq!(interpreter.get_stack(), &[65, 12, -1, -1]);
     }
 
     #[test]
-    fn test_start_moving_randomly() {
-        let input = Cursor::new(Vec::new());
+    fn test_input_limit_endless_reader() {
+        let input = FnReader::new(|| Some(b'x'));
         let output = Cursor::new(Vec::new());
         let gen = StdRng::seed_from_u64(123);
         let mut interpreter = Interpreter::new(input, output, gen);
-        interpreter.load_program("?\n@\n1").unwrap();
+        interpreter.set_input_limit(2);
+        interpreter.load_program(">~:1+v\n^    _@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.get_stack(), &[120, 120, -1]);
     }
 
     #[test]
-    fn test_horizontal_if_when_0() {
+    fn test_get_int_and_push() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("v\n0\n_1@").unwrap();
+        interpreter.input.write_all("65\n".as_bytes()).unwrap();
+        interpreter.input.set_position(0);
+        interpreter.load_program("&@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.stack.pop(), 65);
     }
 
     #[test]
-    fn test_horizontal_if_when_other_than_0() {
+    fn test_get_int_two_on_one_line() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("v\n1\n_@1").unwrap();
+        interpreter.input.write_all("12 34\n".as_bytes()).unwrap();
+        interpreter.input.set_position(0);
+        interpreter.load_program("&&@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.get_stack(), &[12, 34]);
     }
 
     #[test]
-    fn test_vertical_if_when_0() {
+    fn test_get_int_line_endings() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("0|\n 1\n @").unwrap();
+        interpreter
+            .input
+            .write_all("1\r2\r\n-3\n+4".as_bytes())
+            .unwrap();
+        interpreter.input.set_position(0);
+        interpreter.load_program("&&&&@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.get_stack(), &[1, 2, -3, 4]);
     }
 
     #[test]
-    fn test_vertical_if_when_other_than_0() {
+    fn test_get_int_leaves_rest_of_input() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("1|\n @\n 1").unwrap();
+        interpreter.input.write_all("12x\n".as_bytes()).unwrap();
+        interpreter.input.set_position(0);
+        interpreter.load_program("&~@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.get_stack(), &[12, 120]);
     }
 
     #[test]
-    fn test_string_mode() {
+    fn test_get_int_and_push_with_invalid_integer() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("\"HA\"@").unwrap();
+        interpreter.input.write_all("x\n".as_bytes()).unwrap();
+        interpreter.input.set_position(0);
+        interpreter.load_program("&@").unwrap();
 
-        interpreter.run().unwrap();
+        let result = interpreter.run();
 
-        assert_eq!(interpreter.stack.pop(), 65);
-        assert_eq!(interpreter.stack.pop(), 72);
+        match result {
+            Err(InterpreterError::ParseError(_)) => (),
+            _ => assert!(false),
+        }
     }
 
     #[test]
-    fn test_duplicate_top_of_the_stack() {
+    fn test_get_char_and_push() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("1:@").unwrap();
+        interpreter.input.write_all("A\n".as_bytes()).unwrap();
+        interpreter.input.set_position(0);
+        interpreter.load_program("~@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(interpreter.stack.pop(), 65);
     }
 
     #[test]
-    fn test_swap_top_stack_values() {
+    fn test_step_detailed() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("12\\@").unwrap();
+        interpreter.load_program("1+@").unwrap();
 
-        interpreter.run().unwrap();
+        let mut steps = Vec::new();
+        for _ in 0..3 {
+            steps.push(interpreter.step_detailed().unwrap());
+        }
 
-        assert_eq!(interpreter.stack.pop(), 1);
-        assert_eq!(interpreter.stack.pop(), 2);
+        let expected = vec![
+            StepInfo {
+                instruction: '1',
+                pc_before: (0, 0),
+                pc_after: (1, 0),
+                outcome: StepOutcome::Running,
+            },
+            StepInfo {
+                instruction: '+',
+                pc_before: (1, 0),
+                pc_after: (2, 0),
+                outcome: StepOutcome::Running,
+            },
+            StepInfo {
+                instruction: '@',
+                pc_before: (2, 0),
+                pc_after: (0, 0),
+                outcome: StepOutcome::Halted,
+            },
+        ];
+        assert_eq!(steps, expected);
     }
 
     #[test]
-    fn test_pop_and_discard() {
+    fn test_step_back() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("1$@").unwrap();
+        interpreter.set_history_limit(10);
+        interpreter.load_program("1v\n@>2+").unwrap();
+        interpreter.step().unwrap();
+        let stack = interpreter.stack.values().to_vec();
+        let pc = interpreter.pc;
+        let direction = interpreter.direction;
 
-        interpreter.run().unwrap();
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        interpreter.step_back().unwrap();
+        interpreter.step_back().unwrap();
+        interpreter.step_back().unwrap();
 
-        assert!(interpreter.stack.inner.is_empty());
+        assert_eq!(interpreter.stack.values(), stack);
+        assert_eq!(interpreter.pc, pc);
+        assert_eq!(interpreter.direction, direction);
     }
 
     #[test]
-    fn test_pop_and_output_int() {
+    fn test_step_back_restores_put_and_replays_input() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("79*2+.@").unwrap();
-
-        interpreter.run().unwrap();
+        interpreter.input.write_all("AB".as_bytes()).unwrap();
+        interpreter.input.set_position(0);
+        interpreter.set_history_limit(10);
+        interpreter.load_program("~00p@").unwrap();
 
-        let output = String::from_utf8_lossy(interpreter.output.get_ref());
-        assert_eq!(output, "65");
-    }
+        for _ in 0..4 {
+            interpreter.step().unwrap();
+        }
+        for _ in 0..4 {
+            interpreter.step_back().unwrap();
+        }
 
-    #[test]
-    fn test_pop_and_output_char_with_valid_ascii_code() {
-        let mut interpreter = build_interpreter();
-        interpreter.load_program("79*2+,@").unwrap();
+        assert_eq!(interpreter.program[0][0], '~');
+        assert!(interpreter.stack.values().is_empty());
+        assert!(interpreter.self_modifications().is_empty());
 
-        interpreter.run().unwrap();
+        for _ in 0..4 {
+            interpreter.step().unwrap();
+        }
 
-        let output = String::from_utf8_lossy(interpreter.output.get_ref());
-        assert_eq!(output, "A");
+        assert_eq!(interpreter.program[0][0], 'A');
+        assert_eq!(interpreter.self_modifications(), &[(0, 0)]);
     }
 
     #[test]
-    fn test_pop_and_output_char_with_invalid_ascii_code() {
+    fn test_step_back_over_put() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("12-,@").unwrap();
+        interpreter.set_history_limit(10);
+        interpreter.load_program("\"A\"00p\"B\"00p@").unwrap();
 
-        let result = interpreter.run();
+        for _ in 0..12 {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(interpreter.program[0][0], 'B');
+        assert_eq!(interpreter.self_modifications(), &[(0, 0)]);
 
-        match result {
-            Err(InterpreterError::InvalidAscii(-1)) => (),
-            _ => assert!(false),
+        for _ in 0..6 {
+            interpreter.step_back().unwrap();
+        }
+        assert_eq!(interpreter.program[0][0], 'A');
+        assert_eq!(interpreter.self_modifications(), &[(0, 0)]);
+
+        for _ in 0..6 {
+            interpreter.step_back().unwrap();
         }
+        assert_eq!(interpreter.program[0][0], '"');
+        assert!(interpreter.self_modifications().is_empty());
     }
 
     #[test]
-    fn test_bridge() {
+    fn test_halt_hook() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("1@1@").unwrap();
+        let halted = Rc::new(RefCell::new(None));
+        let halted_ = Rc::clone(&halted);
+        interpreter.set_halt_hook(move |interpreter| {
+            *halted_.borrow_mut() = Some(interpreter.get_stack().to_vec());
+        });
+        interpreter.load_program("12@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 1);
+        assert_eq!(*halted.borrow(), Some(vec![1, 2]));
     }
 
     #[test]
-    fn test_put_with_valid_ascii_code() {
+    fn test_self_modifications() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("79*2+21p@\n\n").unwrap();
+        interpreter.load_program("\"A\"01p\"@\"90p1\n ").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.program[1][2], 'A');
+        assert_eq!(interpreter.program[1][0], 'A');
+        assert_eq!(interpreter.self_modifications(), &[(9, 0)]);
     }
 
     #[test]
-    fn test_put_with_invalid_ascii_code() {
+    fn test_render() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("12-21p@\n\n").unwrap();
+        interpreter.load_program("1v\n @").unwrap();
 
-        let result = interpreter.run();
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
 
-        match result {
-            Err(InterpreterError::InvalidAscii(-1)) => (),
-            _ => assert!(false),
-        }
+        assert_eq!(interpreter.render(), "1v\n [@]");
+        assert_eq!(interpreter.to_string(), interpreter.render());
     }
 
     #[test]
-    fn test_put_with_invalid_coordinates() {
+    fn test_run_without_program() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("79*2+89p@\n\n").unwrap();
 
         let result = interpreter.run();
 
-        match result {
-            Err(InterpreterError::InvalidCoordinates { x: 8, y: 9 }) => (),
-            _ => assert!(false),
-        }
+        assert!(matches!(result, Err(InterpreterError::ProgramNotLoaded)));
     }
 
     #[test]
-    fn test_get() {
+    fn test_step_with_empty_program() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("21g@\n  A").unwrap();
+        interpreter.load_program("").unwrap();
 
-        interpreter.run().unwrap();
+        let result = interpreter.step();
 
-        assert_eq!(interpreter.stack.pop(), 65);
+        assert!(matches!(result, Err(InterpreterError::ProgramNotLoaded)));
     }
 
     #[test]
-    fn test_get_with_invalid_coordinates() {
+    fn test_coverage() {
         let mut interpreter = build_interpreter();
-        interpreter.load_program("89g@\n  A").unwrap();
+        interpreter.set_track_coverage(true);
+        interpreter.load_program("0_@\n 1").unwrap();
 
-        let result = interpreter.run();
+        interpreter.run().unwrap();
 
-        match result {
-            Err(InterpreterError::InvalidCoordinates { x: 8, y: 9 }) => (),
-            _ => assert!(false),
-        }
+        let visited = HashSet::from([(0, 0), (1, 0), (2, 0)]);
+        assert_eq!(interpreter.visited_cells(), &visited);
+        assert_eq!(interpreter.coverage_ratio(), 0.75);
     }
 
     #[test]
-    fn test_get_int_and_push() {
+    fn test_echo_input() {
         let mut interpreter = build_interpreter();
-        interpreter.input.write_all("65\n".as_bytes()).unwrap();
+        interpreter.input.write_all("A12\n".as_bytes()).unwrap();
         interpreter.input.set_position(0);
-        interpreter.load_program("&@").unwrap();
+        interpreter.set_echo_input(true);
+        interpreter.load_program("~&.@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 65);
+        let output = String::from_utf8_lossy(interpreter.output_bytes());
+        assert_eq!(output, "A12\n12");
     }
 
     #[test]
-    fn test_get_int_and_push_with_invalid_integer() {
+    fn test_echo_input_counts_towards_output_limit() {
         let mut interpreter = build_interpreter();
-        interpreter.input.write_all("x\n".as_bytes()).unwrap();
+        interpreter.input.write_all(b"AB").unwrap();
         interpreter.input.set_position(0);
-        interpreter.load_program("&@").unwrap();
+        interpreter.set_echo_input(true);
+        interpreter.set_output_limit(2);
+        interpreter.set_output_limit_behavior(OutputLimitBehavior::Halt);
+        interpreter.load_program("~~.@").unwrap();
 
-        let result = interpreter.run();
+        interpreter.run().unwrap();
 
-        match result {
-            Err(InterpreterError::ParseError(_)) => (),
-            _ => assert!(false),
-        }
+        assert_eq!(interpreter.output_bytes(), b"AB");
+        assert_eq!(interpreter.get_stack(), &[65]);
     }
 
     #[test]
-    fn test_get_char_and_push() {
-        let mut interpreter = build_interpreter();
-        interpreter.input.write_all("A\n".as_bytes()).unwrap();
-        interpreter.input.set_position(0);
-        interpreter.load_program("~@").unwrap();
+    fn test_echo_input_halts_on_broken_pipe() {
+        let input = Cursor::new(b"AB".to_vec());
+        let gen = StdRng::seed_from_u64(123);
+        let mut interpreter = Interpreter::new(input, BrokenPipe, gen);
+        interpreter.set_echo_input(true);
+        interpreter.set_halt_on_broken_pipe(true);
+        interpreter.load_program("~~@").unwrap();
 
         interpreter.run().unwrap();
 
-        assert_eq!(interpreter.stack.pop(), 65);
+        assert!(!interpreter.get_enabled());
+        assert_eq!(interpreter.get_stack(), &[65]);
     }
 
     #[test]
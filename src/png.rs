@@ -0,0 +1,82 @@
+use crate::Interpreter;
+use font8x8::{UnicodeFonts, BASIC_FONTS};
+use image::{ImageFormat, ImageResult, Rgb, RgbImage};
+use rand::Rng;
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+
+const CELL_SIZE: u32 = 10;
+const GLYPH_SIZE: u32 = 8;
+const GLYPH_OFFSET: u32 = (CELL_SIZE - GLYPH_SIZE) / 2;
+
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const VISITED: Rgb<u8> = Rgb([220, 220, 220]);
+const PC: Rgb<u8> = Rgb([255, 200, 0]);
+const TEXT: Rgb<u8> = Rgb([0, 0, 0]);
+
+impl<R: BufRead, W: Write, G: Rng> Interpreter<R, W, G> {
+    /// Draws the playfield as a PNG at `path`, one 8x8 glyph per cell, with
+    /// the PC highlighted and cells in [`Interpreter::visited_cells`] shaded.
+    /// Characters outside of ASCII are left blank.
+    pub fn render_png(&self, path: impl AsRef<Path>) -> ImageResult<()> {
+        let width = self.width as u32 * CELL_SIZE;
+        let height = self.height as u32 * CELL_SIZE;
+        let mut image = RgbImage::from_pixel(width, height, BACKGROUND);
+
+        for (y, line) in self.program.iter().enumerate() {
+            for (x, &c) in line.iter().enumerate() {
+                let background = if (x, y) == self.pc() {
+                    PC
+                } else if self.visited_cells.contains(&(x, y)) {
+                    VISITED
+                } else {
+                    BACKGROUND
+                };
+                let glyph = BASIC_FONTS.get(c).unwrap_or([0; 8]);
+
+                let left = x as u32 * CELL_SIZE;
+                let top = y as u32 * CELL_SIZE;
+                for i in 0..CELL_SIZE {
+                    for j in 0..CELL_SIZE {
+                        image.put_pixel(left + j, top + i, background);
+                    }
+                }
+
+                for (i, bits) in (0..).zip(glyph) {
+                    for j in 0..GLYPH_SIZE {
+                        if bits & (1 << j) != 0 {
+                            let (px, py) = (left + GLYPH_OFFSET + j, top + GLYPH_OFFSET + i);
+                            image.put_pixel(px, py, TEXT);
+                        }
+                    }
+                }
+            }
+        }
+
+        image.save_with_format(path, ImageFormat::Png)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::build_interpreter;
+    use std::fs;
+
+    #[test]
+    fn test_render_png() {
+        let mut interpreter = build_interpreter();
+        interpreter.set_track_coverage(true);
+        interpreter.load_program("12+.@").unwrap();
+        interpreter.step().unwrap();
+
+        let file_name = format!("befunge93_rs_test_render_{}.png", std::process::id());
+        let path = std::env::temp_dir().join(file_name);
+        interpreter.render_png(&path).unwrap();
+        let len = fs::metadata(&path).unwrap().len();
+        fs::remove_file(&path).unwrap();
+
+        assert!(len > 0);
+    }
+}
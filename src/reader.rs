@@ -0,0 +1,147 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, Read},
+};
+
+/// Adapts a closure yielding one byte at a time into a [`BufRead`]. The reader
+/// is exhausted once the closure returns `None`.
+pub struct FnReader<F> {
+    f: F,
+    buf: Option<u8>,
+}
+
+impl<F: FnMut() -> Option<u8>> FnReader<F> {
+    pub fn new(f: F) -> Self {
+        let buf = None;
+
+        FnReader { f, buf }
+    }
+}
+
+impl<F: FnMut() -> Option<u8>> Read for FnReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+
+        while n < buf.len() {
+            match self.fill_buf()?.first() {
+                Some(&b) => buf[n] = b,
+                None => break,
+            }
+            self.consume(1);
+            n += 1;
+        }
+
+        Ok(n)
+    }
+}
+
+impl<F: FnMut() -> Option<u8>> BufRead for FnReader<F> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.buf.is_none() {
+            self.buf = (self.f)();
+        }
+
+        Ok(self.buf.as_slice())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if amt > 0 {
+            self.buf = None;
+        }
+    }
+}
+
+/// An in-memory [`BufRead`] that more input can be appended to while it is
+/// being read, e.g. between steps of an interpreter. Reading from an empty
+/// queue reports end of input.
+#[derive(Debug, Default)]
+pub struct QueueReader {
+    inner: VecDeque<u8>,
+}
+
+impl QueueReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        self.inner.extend(bytes);
+    }
+}
+
+impl Read for QueueReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.inner.len());
+
+        for (dst, src) in buf.iter_mut().zip(self.inner.drain(..n)) {
+            *dst = src;
+        }
+
+        Ok(n)
+    }
+}
+
+impl BufRead for QueueReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.inner.as_slices().0)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.drain(..amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::build_interpreter_with_input, InterpreterError};
+
+    #[test]
+    fn test_fn_reader_read_line() {
+        let mut bytes = b"12\n34".iter().copied();
+        let mut reader = FnReader::new(move || bytes.next());
+
+        let mut s = String::new();
+        reader.read_line(&mut s).unwrap();
+
+        assert_eq!(s, "12\n");
+    }
+
+    #[test]
+    fn test_fn_reader_drives_get_char_and_push() {
+        let mut bytes = b"AB".iter().copied();
+        let input = FnReader::new(move || bytes.next());
+        let mut interpreter = build_interpreter_with_input(input);
+        interpreter.load_program("~~~@").unwrap();
+
+        let result = interpreter.run();
+
+        assert!(matches!(result, Err(InterpreterError::IoError(_))));
+        assert_eq!(interpreter.get_stack(), &[65, 66]);
+    }
+
+    #[test]
+    fn test_queue_reader_fed_between_steps() {
+        let mut interpreter = build_interpreter_with_input(QueueReader::new());
+        interpreter.load_program("~~@").unwrap();
+
+        interpreter.get_input_mut().push_bytes(b"A");
+        interpreter.step().unwrap();
+        interpreter.get_input_mut().push_bytes(b"B");
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.get_stack(), &[65, 66]);
+    }
+
+    #[test]
+    fn test_queue_reader_read_line() {
+        let mut reader = QueueReader::new();
+        reader.push_bytes(b"12\n34");
+
+        let mut s = String::new();
+        reader.read_line(&mut s).unwrap();
+
+        assert_eq!(s, "12\n");
+        assert_eq!(reader.inner, b"34");
+    }
+}
@@ -0,0 +1,52 @@
+use crate::Interpreter;
+use rand::rngs::ThreadRng;
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct WasmInterpreter {
+    inner: Interpreter<Cursor<Vec<u8>>, Cursor<Vec<u8>>, ThreadRng>,
+}
+
+#[wasm_bindgen]
+impl WasmInterpreter {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        let input = Cursor::new(Vec::new());
+        let output = Cursor::new(Vec::new());
+        let gen = rand::thread_rng();
+        let inner = Interpreter::new(input, output, gen);
+
+        WasmInterpreter { inner }
+    }
+
+    pub fn load_program(&mut self, src: &str) -> Result<(), JsError> {
+        self.inner.load_program(src)?;
+        self.inner.set_output(Cursor::new(Vec::new()));
+
+        Ok(())
+    }
+
+    pub fn run(&mut self, input: &str) -> Result<String, JsError> {
+        let output = self.inner.run_with_input_bytes(input.as_bytes().to_vec())?;
+        self.inner.set_output(Cursor::new(output));
+
+        Ok(self.output())
+    }
+
+    pub fn step(&mut self) -> Result<bool, JsError> {
+        self.inner.step()?;
+
+        Ok(self.inner.get_enabled())
+    }
+
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(self.inner.output_bytes()).into_owned()
+    }
+}
+
+impl Default for WasmInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,246 @@
+use std::{path::PathBuf, process::Command};
+
+const BIN: &str = env!("CARGO_BIN_EXE_befunge93");
+
+/// A program written to the temp dir under a name unique to the test and
+/// process, removed again when dropped.
+struct TempProgram {
+    path: PathBuf,
+}
+
+impl TempProgram {
+    fn new(name: &str, source: &str) -> Self {
+        let file_name = format!("befunge93-rs-{}-{}.bf", name, std::process::id());
+        let path = std::env::temp_dir().join(file_name);
+        std::fs::write(&path, source).unwrap();
+
+        TempProgram { path }
+    }
+}
+
+impl Drop for TempProgram {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[test]
+fn test_bad_path_writes_nothing_to_stdout() {
+    let output = Command::new(BIN).arg("does/not/exist.bf").output().unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(!output.stderr.is_empty());
+}
+
+#[test]
+fn test_quiet_suppresses_diagnostics() {
+    let output = Command::new(BIN)
+        .args(["--quiet", "does/not/exist.bf"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_usage_goes_to_stderr() {
+    let output = Command::new(BIN).arg("--bogus").output().unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Usage:"));
+}
+
+#[test]
+fn test_grid_size() {
+    let program = TempProgram::new("grid-size", "\"A\"99*2-46*p99*2-46*g,@");
+
+    let output = Command::new(BIN)
+        .args(["--grid-size", "80x25"])
+        .arg(&program.path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"A");
+}
+
+#[test]
+fn test_verbose_reports_halt_reason() {
+    let program = TempProgram::new("verbose", "@");
+
+    let output = Command::new(BIN)
+        .arg("--verbose")
+        .arg(&program.path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(output.stderr, b"halt reason: Halted, pc: (0, 0)\n");
+}
+
+#[test]
+fn test_verbose_reports_step_limit() {
+    let program = TempProgram::new("step-limit", "v\n>");
+
+    let output = Command::new(BIN)
+        .args(["--verbose", "--step-limit", "3"])
+        .arg(&program.path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stderr, b"halt reason: StepLimit, pc: (0, 1)\n");
+}
+
+#[test]
+fn test_trace_without_color() {
+    let program = TempProgram::new("trace", "12+.@");
+
+    let output = Command::new(BIN)
+        .args(["--trace", "--color", "never"])
+        .arg(&program.path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"3");
+    assert!(!output.stderr.contains(&b'\x1b'));
+    assert_eq!(
+        output.stderr,
+        b"(0, 0) 1\n(1, 0) 2\n(2, 0) +\n(3, 0) .\n(4, 0) @\n"
+    );
+}
+
+#[test]
+fn test_trace_with_color() {
+    let program = TempProgram::new("trace-color", "1@");
+
+    let output = Command::new(BIN)
+        .args(["--trace", "--color", "always"])
+        .arg(&program.path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stderr, b"(0, 0) 1\n(1, 0) \x1b[31m@\x1b[0m\n");
+}
+
+#[test]
+fn test_lint_warns_about_missing_halt() {
+    let program = TempProgram::new("lint", "\"@\",");
+
+    let output = Command::new(BIN)
+        .args(["--lint", "--step-limit", "5"])
+        .arg(&program.path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        output.stderr,
+        b"warning: program has no `@` outside of strings and may loop forever\n"
+    );
+}
+
+#[test]
+fn test_trace_with_step_limit_and_verbose() {
+    let program = TempProgram::new("trace-step-limit", "v\n>");
+
+    let output = Command::new(BIN)
+        .args([
+            "--trace",
+            "--color",
+            "never",
+            "--verbose",
+            "--step-limit",
+            "2",
+        ])
+        .arg(&program.path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        output.stderr,
+        b"(0, 0) v\n(0, 1) >\nhalt reason: StepLimit, pc: (0, 1)\n"
+    );
+}
+
+#[test]
+fn test_animate_with_step_limit_and_stats() {
+    let program = TempProgram::new("animate-step-limit", "v\n>");
+
+    let output = Command::new(BIN)
+        .args([
+            "--animate",
+            "--animate-delay",
+            "0",
+            "--stats",
+            "--step-limit",
+            "3",
+        ])
+        .arg(&program.path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("steps: 3,"));
+}
+
+#[test]
+fn test_newline_only_on_halt() {
+    let program = TempProgram::new("newline", "1.v\n  >");
+
+    let output = Command::new(BIN)
+        .args(["--newline", "--step-limit", "3"])
+        .arg(&program.path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"1");
+
+    std::fs::write(&program.path, "1.@").unwrap();
+    let output = Command::new(BIN)
+        .arg("--newline")
+        .arg(&program.path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"1\n");
+}
+
+#[test]
+fn test_stats_after_error() {
+    let program = TempProgram::new("stats-error", "1x");
+
+    let output = Command::new(BIN)
+        .arg("--stats")
+        .arg(&program.path)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("steps: 1,"));
+    assert!(stderr.contains("Error:"));
+}
+
+#[test]
+fn test_profile_after_error() {
+    let program = TempProgram::new("profile-error", "1x");
+
+    let output = Command::new(BIN)
+        .arg("--profile")
+        .arg(&program.path)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Hottest cells:\n  x: 0, y: 0, instruction: '1', count: 1\n"));
+}